@@ -7,25 +7,150 @@ pub enum Fragment {
     Str(String),
     Name,
     Hash,
+    /// `[hash:N]` the module hash truncated to N characters.
+    HashLen(usize),
+    File,
+    Dir,
+    Crate,
+    Path,
+}
+
+/// Values that the placeholders of a class name pattern expand to for a given css module.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PatternContext {
+    /// Module hash truncated to `hash_len`.
+    pub hash: String,
+    /// Module hash without truncation, used by `[hash:N]`.
+    pub full_hash: String,
+    /// File name of the css module without its last extension.
+    pub file: String,
+    /// Name of the directory containing the css module.
+    pub dir: String,
+    pub crate_name: String,
+    /// Path of the css module relative to `hash_root_path`, without its last extension.
+    pub path: String,
+}
+
+impl PatternContext {
+    /// Replaces every character that isn't allowed in a css identifier with `_`.
+    pub fn sanitize(value: &str) -> String {
+        value
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ClassNamePattern(Vec<Fragment>);
 
 impl ClassNamePattern {
-    pub fn apply(&self, classname: &str, hash: &str) -> String {
+    pub fn apply(&self, classname: &str, context: &PatternContext) -> String {
         self.0
             .iter()
             .map(|v| match v {
                 Fragment::Str(s) => s,
                 Fragment::Name => classname,
-                Fragment::Hash => hash,
+                Fragment::Hash => &context.hash,
+                Fragment::HashLen(len) => &context.full_hash[..(*len).min(context.full_hash.len())],
+                Fragment::File => &context.file,
+                Fragment::Dir => &context.dir,
+                Fragment::Crate => &context.crate_name,
+                Fragment::Path => &context.path,
             })
             .collect::<Vec<_>>()
             .join("")
     }
 }
 
+impl ClassNamePattern {
+    /// Checks that the pattern can only produce valid css identifiers.
+    ///
+    /// Placeholders expand to identifier characters, so only the literal parts of the
+    /// pattern need to be checked. Placeholders other than `[name]` may still expand to a
+    /// value starting with a digit, those are escaped by [`escape_identifier`] when writing css.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.0.is_empty() {
+            return Err("the pattern is empty".into());
+        }
+
+        for fragment in &self.0 {
+            let Fragment::Str(s) = fragment else {
+                continue;
+            };
+
+            if let Some(c) = s.chars().find(|c| !is_identifier_char(*c)) {
+                return Err(format!(
+                    "{c:?} is not allowed in a css class name, only letters, digits, `-` and `_` are"
+                ));
+            }
+        }
+
+        if let Some(Fragment::Str(s)) = self.0.first() {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), _) if c.is_ascii_digit() => {
+                    return Err("css class names can't start with a digit".into())
+                }
+                (Some('-'), Some(c)) if c.is_ascii_digit() => {
+                    return Err("css class names can't start with `-` followed by a digit".into())
+                }
+                (Some('-'), None) if self.0.len() == 1 => {
+                    return Err("`-` is not a valid css class name".into())
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_' || !c.is_ascii()
+}
+
+/// Escapes a class name produced by a [`ClassNamePattern`] so that it can be used in css.
+///
+/// Only the start of the name needs escaping since the rest is validated by
+/// [`ClassNamePattern::validate`].
+pub fn escape_identifier(name: &str) -> Cow<'_, str> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), _) if c.is_ascii_digit() => {
+            Cow::Owned(format!("\\{:x} {}", c as u32, &name[1..]))
+        }
+        (Some('-'), Some(c)) if c.is_ascii_digit() => {
+            Cow::Owned(format!("-\\{:x} {}", c as u32, &name[2..]))
+        }
+        _ => Cow::Borrowed(name),
+    }
+}
+
+impl std::fmt::Display for ClassNamePattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for fragment in &self.0 {
+            match fragment {
+                Fragment::Str(s) => f.write_str(s)?,
+                Fragment::Name => f.write_str("[name]")?,
+                Fragment::Hash => f.write_str("[hash]")?,
+                Fragment::HashLen(len) => write!(f, "[hash:{len}]")?,
+                Fragment::File => f.write_str("[file]")?,
+                Fragment::Dir => f.write_str("[dir]")?,
+                Fragment::Crate => f.write_str("[crate]")?,
+                Fragment::Path => f.write_str("[path]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
 impl Default for ClassNamePattern {
     fn default() -> Self {
         Self(vec![
@@ -53,15 +178,51 @@ impl<'de> Deserialize<'de> for ClassNamePattern {
 mod parse {
     use super::*;
     use winnow::{
-        combinator::{alt, repeat},
+        ascii::dec_uint,
+        combinator::{alt, cut_err, delimited, preceded, repeat},
         error::{ContextError, ParseError},
         token::take_till,
         ModalResult, Parser,
     };
-    fn fragment(input: &mut &str) -> ModalResult<Fragment> {
+
+    #[derive(Debug)]
+    struct UnknownPlaceholder(String);
+
+    impl std::fmt::Display for UnknownPlaceholder {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(
+                f,
+                "unknown placeholder `[{}]`, expected one of [name], [hash], [hash:N], [file], [dir], [crate], [path]",
+                self.0
+            )
+        }
+    }
+
+    impl std::error::Error for UnknownPlaceholder {}
+
+    fn placeholder(input: &mut &str) -> ModalResult<Fragment> {
         alt((
             "[name]".value(Fragment::Name),
             "[hash]".value(Fragment::Hash),
+            delimited("[hash:", dec_uint, ']').map(Fragment::HashLen),
+            "[file]".value(Fragment::File),
+            "[dir]".value(Fragment::Dir),
+            "[crate]".value(Fragment::Crate),
+            "[path]".value(Fragment::Path),
+            preceded(
+                '[',
+                cut_err(
+                    take_till(0.., ']')
+                        .try_map(|token: &str| Err(UnknownPlaceholder(token.to_owned()))),
+                ),
+            ),
+        ))
+        .parse_next(input)
+    }
+
+    fn fragment(input: &mut &str) -> ModalResult<Fragment> {
+        alt((
+            placeholder,
             take_till(1.., '[').map(|s: &str| Fragment::Str(s.into())),
         ))
         .parse_next(input)
@@ -79,13 +240,81 @@ mod parse {
 #[cfg(test)]
 mod test {
 
-    use crate::class_name_pattern::ClassNamePattern;
+    use crate::class_name_pattern::{escape_identifier, ClassNamePattern, PatternContext};
 
     #[test]
     fn test_pattern_deserialize() {
         let pattern: ClassNamePattern =
             serde_json::from_str("\"test-[name]-[hash]\"").expect("should deserialize");
 
-        assert_eq!("test-my-class-12345", pattern.apply("my-class", "12345"));
+        let context = PatternContext {
+            hash: "12345".into(),
+            ..Default::default()
+        };
+        assert_eq!("test-my-class-12345", pattern.apply("my-class", &context));
+    }
+
+    #[test]
+    fn test_pattern_placeholders() {
+        let pattern: ClassNamePattern =
+            serde_json::from_str("\"[crate]_[path]_[dir]_[file]__[name]--[hash:4]-[hash:99]\"")
+                .expect("should deserialize");
+
+        let context = PatternContext {
+            hash: "a1b2c3d".into(),
+            full_hash: "a1b2c3d4e5f6".into(),
+            file: "card_module".into(),
+            dir: "components".into(),
+            crate_name: "my-app".into(),
+            path: "src_components_card_module".into(),
+        };
+        assert_eq!(
+            "my-app_src_components_card_module_components_card_module__header--a1b2-a1b2c3d4e5f6",
+            pattern.apply("header", &context)
+        );
+    }
+
+    #[test]
+    fn test_pattern_unknown_placeholder() {
+        let err = serde_json::from_str::<ClassNamePattern>("\"[name]-[hsah]\"")
+            .expect_err("unknown placeholders should be rejected");
+        assert!(
+            err.to_string().contains("unknown placeholder `[hsah]`"),
+            "unexpected error message: {err}"
+        );
+
+        serde_json::from_str::<ClassNamePattern>("\"[hash:x]\"")
+            .expect_err("[hash:N] requires a number");
+    }
+
+    #[test]
+    fn test_pattern_validate() {
+        let validate = |pattern: &str| {
+            serde_json::from_str::<ClassNamePattern>(&format!("\"{pattern}\""))
+                .expect("should deserialize")
+                .validate()
+        };
+
+        assert_eq!(validate("[name]-[hash]"), Ok(()));
+        assert_eq!(validate("x[hash]_[name]"), Ok(()));
+        assert_eq!(validate("[hash]-[name]"), Ok(()));
+        assert_eq!(validate("--[name]"), Ok(()));
+        assert_eq!(validate("-[hash]"), Ok(()));
+        assert_eq!(validate("é-[name]"), Ok(()));
+
+        assert!(validate("").is_err());
+        assert!(validate("1-[name]").is_err());
+        assert!(validate("-1[name]").is_err());
+        assert!(validate("-").is_err());
+        assert!(validate("[name] [hash]").is_err());
+        assert!(validate("[name].[hash]").is_err());
+    }
+
+    #[test]
+    fn test_escape_identifier() {
+        assert_eq!(escape_identifier("foo-1a2b"), "foo-1a2b");
+        assert_eq!(escape_identifier("1a2b-foo"), "\\31 a2b-foo");
+        assert_eq!(escape_identifier("-1a2b"), "-\\31 a2b");
+        assert_eq!(escape_identifier("--1a2b"), "--1a2b");
     }
 }
@@ -0,0 +1,226 @@
+//! Generation of the items and the runtime registration of a style module, shared by the
+//! import macros and `stylance::build` so that both generate the same code.
+
+use std::path::Path;
+
+use anyhow::bail;
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::{quote, quote_spanned};
+
+use crate::{path_utils, Config, GetClassesResult};
+
+/// Generates one `&str` constant per class and the `keyframes`, `vars` and `exports`
+/// submodules of a style module. The identifiers are created with `span`.
+pub fn module_items(classes: &GetClassesResult, span: Span) -> TokenStream {
+    let class_constants = constants(
+        classes.classes.iter().map(|class| {
+            let value = std::iter::once(&class.hashed_name)
+                .chain(&class.composes)
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(" ");
+            (class.original_name.as_str(), value)
+        }),
+        span,
+    );
+
+    let keyframes_module = constants_module(
+        "keyframes",
+        classes
+            .keyframes
+            .iter()
+            .map(|k| (k.original_name.as_str(), k.hashed_name.clone())),
+        span,
+    );
+    let vars_module = constants_module(
+        "vars",
+        classes
+            .vars
+            .iter()
+            .map(|v| (v.original_name.as_str(), v.hashed_name.clone())),
+        span,
+    );
+    let exports_module = constants_module(
+        "exports",
+        classes
+            .exports
+            .iter()
+            .map(|e| (e.name.as_str(), e.value.clone())),
+        span,
+    );
+
+    quote! {
+        #class_constants
+        #keyframes_module
+        #vars_module
+        #exports_module
+    }
+}
+
+/// Registers the module in `stylance::registry`. When `embed_css` is enabled it also
+/// generates the `CSS` constant and registers it in `stylance::embed`, which fails for
+/// sass modules.
+///
+/// The registrations go through `stylance::internal::register!`, which drops them when the
+/// `registry` feature of stylance is disabled.
+pub fn registration(
+    file_path: &Path,
+    config: &Config,
+    classes: &GetClassesResult,
+) -> anyhow::Result<TokenStream> {
+    let hash_root = path_utils::normalize(&config.hash_root_path)?;
+    let path = path_utils::diff_normalized_paths(path_utils::normalize(file_path)?, hash_root)?
+        .iter()
+        .map(|c| c.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+
+    let hash = &classes.hash;
+    let classes = classes.classes.iter().map(|class| {
+        let original_name = &class.original_name;
+        let hashed_name = &class.hashed_name;
+        let composes = &class.composes;
+        quote! {
+            ::stylance::registry::Class {
+                original_name: #original_name,
+                hashed_name: #hashed_name,
+                composes: &[#(#composes),*],
+            }
+        }
+    });
+
+    let (css_constant, css) = if config.embed_css {
+        if file_path
+            .extension()
+            .is_some_and(|ext| ext == "scss" || ext == "sass")
+        {
+            bail!(
+                "{} can't be embedded, embed_css only supports .css modules since sass modules aren't compiled",
+                file_path.to_string_lossy()
+            );
+        }
+        let css = crate::load_and_modify_css(file_path, config)?.contents;
+        (
+            quote! {
+                pub const CSS: &str = #css;
+                ::stylance::internal::register! {
+                    ::stylance::embed::EmbeddedStyle { path: #path, hash: #hash, css: CSS }
+                }
+            },
+            quote! { ::core::option::Option::Some(CSS) },
+        )
+    } else {
+        (TokenStream::new(), quote! { ::core::option::Option::None })
+    };
+
+    Ok(quote! {
+        #css_constant
+        ::stylance::internal::register! {
+            ::stylance::registry::Module {
+                path: #path,
+                hash: #hash,
+                classes: &[#(#classes,)*],
+                css: #css,
+            }
+        }
+    })
+}
+
+/// Turns a css name into the identifier of its constant or module.
+///
+/// Characters other than ascii letters and digits are replaced with `_`, a leading digit
+/// gets a `_` prefix, keywords become raw identifiers and the keywords that can't be raw
+/// (`crate`, `self`, `super`, `Self` and `_`) get a `_` suffix.
+pub fn ident(name: &str, span: Span) -> Ident {
+    let mut ident = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+
+    if matches!(ident.as_str(), "crate" | "self" | "super" | "Self" | "_") {
+        ident.push('_');
+    }
+
+    if KEYWORDS.contains(&ident.as_str()) {
+        Ident::new_raw(&ident, span)
+    } else {
+        Ident::new(&ident, span)
+    }
+}
+
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn",
+    "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in", "let",
+    "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return",
+    "static", "struct", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use",
+    "virtual", "where", "while", "yield",
+];
+
+fn constants<'a>(
+    constants: impl IntoIterator<Item = (&'a str, String)>,
+    span: Span,
+) -> TokenStream {
+    constants
+        .into_iter()
+        .map(|(name, value)| {
+            let ident = ident(name, span);
+            quote_spanned!(span =>
+                #[allow(non_upper_case_globals)]
+                pub const #ident: &str = #value;
+            )
+        })
+        .collect()
+}
+
+/// Generates a submodule with one `&str` constant per `(name, value)` pair, or nothing if
+/// there are no pairs.
+fn constants_module<'a>(
+    module_name: &str,
+    constants: impl IntoIterator<Item = (&'a str, String)>,
+    span: Span,
+) -> TokenStream {
+    let mut constants = constants.into_iter().peekable();
+    if constants.peek().is_none() {
+        return TokenStream::new();
+    }
+
+    let module_ident = Ident::new(module_name, span);
+    let constants = self::constants(constants, span);
+
+    quote! {
+        #[allow(dead_code)]
+        pub mod #module_ident {
+            #constants
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ident() {
+        let ident = |name| ident(name, Span::call_site()).to_string();
+
+        assert_eq!(ident("header"), "header");
+        assert_eq!(ident("style-with-dashes"), "style_with_dashes");
+        assert_eq!(ident("-webkit-thing"), "_webkit_thing");
+        assert_eq!(ident("2xl"), "_2xl");
+        assert_eq!(ident("type"), "r#type");
+        assert_eq!(ident("async"), "r#async");
+        assert_eq!(ident("self"), "self_");
+        assert_eq!(ident("Self"), "Self_");
+        assert_eq!(ident("crate"), "crate_");
+        assert_eq!(ident("super"), "super_");
+        assert_eq!(ident("_"), "__");
+        assert_eq!(ident(""), "__");
+        assert_eq!(ident("size²"), "size_");
+        assert_eq!(ident("a.b"), "a_b");
+        assert_eq!(ident("café"), "caf_");
+    }
+}
@@ -7,7 +7,37 @@ use std::{
 use anyhow::{bail, Context};
 use serde::Deserialize;
 
-use crate::{class_name_pattern::ClassNamePattern, path_utils::normalize};
+use crate::{
+    class_name_pattern::ClassNamePattern,
+    glob::GlobList,
+    minify::MinifiedNames,
+    normalize_separators,
+    path_utils::{diff_normalized_paths, normalize},
+};
+
+/// Whether class names are scoped to the css module or left alone.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    #[default]
+    Local,
+    Global,
+}
+
+/// What is hashed to produce the hash of a css module.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HashStrategy {
+    /// The path of the css module relative to `hash_root_path`.
+    #[default]
+    #[serde(rename = "path")]
+    Path,
+    /// The contents of the css module file.
+    #[serde(rename = "content")]
+    Content,
+    /// Both the relative path and the contents of the css module file.
+    #[serde(rename = "path+content")]
+    PathAndContent,
+}
 
 fn default_extensions() -> Vec<String> {
     vec![".module.css".to_owned(), ".module.scss".to_owned()]
@@ -21,17 +51,35 @@ fn default_hash_len() -> usize {
     7
 }
 
+fn default_minified_names_file() -> PathBuf {
+    PathBuf::from("stylance.lock")
+}
+
 #[derive(Deserialize, Debug, Default, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct PartialConfig {
     pub output_file: Option<PathBuf>,
     pub output_dir: Option<PathBuf>,
+    pub manifest_file: Option<PathBuf>,
+    pub source_map: Option<bool>,
     pub extensions: Option<Vec<String>>,
     pub folders: Option<Vec<PathBuf>>,
+    pub include: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
     pub scss_prelude: Option<String>,
+    pub compile_scss: Option<bool>,
+    pub scss_load_paths: Option<Vec<PathBuf>>,
+    pub minify: Option<bool>,
+    pub targets: Option<Vec<String>>,
     pub hash_len: Option<usize>,
+    pub hash_strategy: Option<HashStrategy>,
     pub class_name_pattern: Option<ClassNamePattern>,
     pub hash_root_path: Option<PathBuf>,
+    pub scoped_custom_properties: Option<bool>,
+    pub default_scope: Option<Scope>,
+    pub minify_names: Option<bool>,
+    pub minified_names_file: Option<PathBuf>,
+    pub embed_css: Option<bool>,
     #[serde(default)]
     pub workspace: bool,
 }
@@ -41,17 +89,49 @@ pub struct PartialConfig {
  * Unlike PartialConfig, the paths in this struct should be interpreted
  * as relative to CWD instead of relative to a manifest dir.
  */
+#[derive(Clone)]
 pub struct Config {
     pub manifest_dir: PathBuf,
+    /// Package name from Cargo.toml, only set when the config is loaded with `Config::load`.
+    pub crate_name: Option<String>,
     pub workspace_dir: Option<PathBuf>,
     pub output_file: Option<PathBuf>,
     pub output_dir: Option<PathBuf>,
+    /// JSON file listing the scoped classes of every module.
+    pub manifest_file: Option<PathBuf>,
+    /// Whether a source map is written next to `output_file`.
+    pub source_map: bool,
     pub extensions: Vec<String>,
     pub folders: Vec<PathBuf>,
+    /// Files inside of `folders` that are css modules even if they don't end with one of the `extensions`.
+    /// The globs match paths relative to `manifest_dir`.
+    pub include: GlobList,
+    /// Files inside of `folders` that are never css modules.
+    /// The globs match paths relative to `manifest_dir`.
+    pub exclude: GlobList,
     pub scss_prelude: Option<String>,
+    /// Whether `output_file` is compiled from scss to css.
+    pub compile_scss: bool,
+    /// Extra load paths used to resolve imports when compiling scss.
+    pub scss_load_paths: Vec<PathBuf>,
+    /// Whether `output_file` is minified.
+    pub minify: bool,
+    /// Browserslist queries of the browsers `output_file` should support,
+    /// vendor prefixes are added for them.
+    pub targets: Vec<String>,
     pub hash_len: usize,
+    pub hash_strategy: HashStrategy,
     pub class_name_pattern: ClassNamePattern,
     pub hash_root_path: PathBuf,
+    pub scoped_custom_properties: bool,
+    pub default_scope: Scope,
+    pub minify_names: bool,
+    /// Lock file where the minified names are stored.
+    pub minified_names_file: PathBuf,
+    /// Names loaded from `minified_names_file`, empty unless `minify_names` is enabled.
+    pub minified_names: MinifiedNames,
+    /// Whether the import macros include the transformed css of the module as a `CSS` constant.
+    pub embed_css: bool,
 }
 
 impl Config {
@@ -60,6 +140,8 @@ impl Config {
             .context("Failed to read Cargo.toml")?;
         let mut cargo_toml: CargoToml = toml::from_str(&cargo_toml_contents)?;
 
+        let crate_name = cargo_toml.package.as_mut().and_then(|p| p.name.take());
+
         let config = cargo_toml
             .package
             .as_mut()
@@ -81,7 +163,9 @@ impl Config {
             None
         };
 
-        Self::from_partials(manifest_dir, config, workspace)
+        let mut config = Self::from_partials(manifest_dir, config, workspace)?;
+        config.crate_name = crate_name;
+        Ok(config)
     }
 
     pub fn from_partials(
@@ -99,11 +183,47 @@ impl Config {
                 );
                 ws_config.output_file = ws_config.output_file.map(|p| workspace_dir.join(p));
                 ws_config.output_dir = ws_config.output_dir.map(|p| workspace_dir.join(p));
+                ws_config.manifest_file = ws_config.manifest_file.map(|p| workspace_dir.join(p));
+                ws_config.scss_load_paths = ws_config
+                    .scss_load_paths
+                    .map(|paths| paths.into_iter().map(|p| workspace_dir.join(p)).collect());
+                ws_config.minified_names_file = Some(
+                    workspace_dir.join(
+                        ws_config
+                            .minified_names_file
+                            .unwrap_or_else(default_minified_names_file),
+                    ),
+                );
                 (Some(workspace_dir), ws_config)
             }
             None => (None, PartialConfig::default()),
         };
 
+        let minify_names = config
+            .minify_names
+            .or(ws_config.minify_names)
+            .unwrap_or(false);
+        let minified_names_file = manifest_dir.join(
+            config
+                .minified_names_file
+                .or(ws_config.minified_names_file)
+                .unwrap_or_else(default_minified_names_file),
+        );
+        let minified_names = if minify_names {
+            MinifiedNames::load(&minified_names_file)?
+        } else {
+            MinifiedNames::default()
+        };
+
+        let include = config.include.or(ws_config.include).unwrap_or_default();
+        let include = GlobList::new(include.clone()).map_err(|e| {
+            anyhow::anyhow!("Stylance config include patterns {include:?} are invalid: {e}")
+        })?;
+        let exclude = config.exclude.or(ws_config.exclude).unwrap_or_default();
+        let exclude = GlobList::new(exclude.clone()).map_err(|e| {
+            anyhow::anyhow!("Stylance config exclude patterns {exclude:?} are invalid: {e}")
+        })?;
+
         let config = Self {
             output_file: config
                 .output_file
@@ -113,6 +233,11 @@ impl Config {
                 .output_dir
                 .or(ws_config.output_dir)
                 .map(|p| manifest_dir.join(p)),
+            manifest_file: config
+                .manifest_file
+                .or(ws_config.manifest_file)
+                .map(|p| manifest_dir.join(p)),
+            source_map: config.source_map.or(ws_config.source_map).unwrap_or(false),
             extensions: config
                 .extensions
                 .or(ws_config.extensions)
@@ -124,11 +249,30 @@ impl Config {
                 .into_iter()
                 .map(|p| manifest_dir.join(p))
                 .collect(),
+            include,
+            exclude,
             scss_prelude: config.scss_prelude.or(ws_config.scss_prelude),
+            compile_scss: config
+                .compile_scss
+                .or(ws_config.compile_scss)
+                .unwrap_or(false),
+            scss_load_paths: config
+                .scss_load_paths
+                .or(ws_config.scss_load_paths)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|p| manifest_dir.join(p))
+                .collect(),
+            minify: config.minify.or(ws_config.minify).unwrap_or(false),
+            targets: config.targets.or(ws_config.targets).unwrap_or_default(),
             hash_len: config
                 .hash_len
                 .or(ws_config.hash_len)
                 .unwrap_or(default_hash_len()),
+            hash_strategy: config
+                .hash_strategy
+                .or(ws_config.hash_strategy)
+                .unwrap_or_default(),
             class_name_pattern: config
                 .class_name_pattern
                 .or(ws_config.class_name_pattern)
@@ -138,16 +282,62 @@ impl Config {
                 .or(ws_config.hash_root_path)
                 .map(|p| manifest_dir.join(p))
                 .unwrap_or_else(|| manifest_dir.to_path_buf()),
+            scoped_custom_properties: config
+                .scoped_custom_properties
+                .or(ws_config.scoped_custom_properties)
+                .unwrap_or(false),
+            default_scope: config
+                .default_scope
+                .or(ws_config.default_scope)
+                .unwrap_or_default(),
+            minify_names,
+            minified_names_file,
+            minified_names,
+            embed_css: config.embed_css.or(ws_config.embed_css).unwrap_or(false),
             workspace_dir,
             manifest_dir,
+            crate_name: None,
         };
 
         if config.extensions.iter().any(|e| e.is_empty()) {
             bail!("Stylance config extensions can't be empty strings");
         }
 
+        if let Err(reason) = config.class_name_pattern.validate() {
+            bail!(
+                "Stylance config class_name_pattern \"{}\" is invalid: {reason}",
+                config.class_name_pattern
+            );
+        }
+
         Ok(config)
     }
+
+    /// Whether a file found inside of `folders` is a css module.
+    ///
+    /// It must end with one of the `extensions` or match an `include` pattern, and not match
+    /// an `exclude` pattern.
+    pub fn is_css_module(&self, path: &Path) -> bool {
+        let relative_path = self.manifest_relative_path(path);
+        let path_str = path.to_string_lossy();
+        (self.extensions.iter().any(|ext| path_str.ends_with(ext))
+            || self.include.is_match(&relative_path))
+            && !self.exclude.is_match(&relative_path)
+    }
+
+    /// Returns the `exclude` pattern that matches a file, if any.
+    pub fn excluded_by(&self, path: &Path) -> Option<&str> {
+        self.exclude.first_match(&self.manifest_relative_path(path))
+    }
+
+    /// Path with `/` separators relative to `manifest_dir`, which is what the globs match against.
+    fn manifest_relative_path(&self, path: &Path) -> String {
+        let relative_path = normalize(path)
+            .and_then(|path| Ok((path, normalize(&self.manifest_dir)?)))
+            .ok()
+            .and_then(|(path, manifest_dir)| diff_normalized_paths(&path, &manifest_dir).ok());
+        normalize_separators(relative_path.as_deref().unwrap_or(path))
+    }
 }
 
 #[derive(Deserialize)]
@@ -158,6 +348,7 @@ struct CargoToml {
 
 #[derive(Deserialize)]
 struct CargoTomlPackage {
+    name: Option<String>,
     metadata: Option<CargoTomlPackageMetadata>,
     /// Explicit workspace path, e.g. `workspace = "../my-workspace"`
     #[serde(rename = "workspace")]
@@ -0,0 +1,129 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// Error produced when a css module file can't be parsed.
+///
+/// Its Display implementation renders the error in rustc style:
+/// ```text
+/// unexpected end of block
+///  --> src/style.module.css:3:5
+///   |
+/// 3 |     .foo {
+///   |     ^
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub path: PathBuf,
+    /// 1 based line number.
+    pub line: usize,
+    /// 1 based column number, counted in characters.
+    pub column: usize,
+    /// Contents of the line where the error happened.
+    pub snippet: String,
+    pub message: String,
+}
+
+impl ParseError {
+    /// Creates an error located at the byte `offset` of the `source` file contents.
+    ///
+    /// An offset in the middle of a character points to the start of that character.
+    pub fn new(path: &Path, source: &str, offset: usize, message: String) -> Self {
+        let mut offset = offset.min(source.len());
+        while !source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let (line, column) = line_column(source, offset);
+        let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[offset..]
+            .find('\n')
+            .map_or(source.len(), |i| offset + i);
+
+        Self {
+            path: path.to_path_buf(),
+            line,
+            column,
+            snippet: source[line_start..line_end]
+                .trim_end_matches('\r')
+                .to_owned(),
+            message,
+        }
+    }
+}
+
+/// Converts a byte offset into 1 based line and column numbers, the column is counted in characters.
+pub(crate) fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    (
+        source[..offset].matches('\n').count() + 1,
+        source[line_start..offset].chars().count() + 1,
+    )
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let line_number = self.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+
+        // Reuse the whitespace of the snippet so that tabs keep the caret aligned.
+        let caret_indent = self
+            .snippet
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+
+        writeln!(f, "{}", self.message)?;
+        writeln!(
+            f,
+            "{gutter}--> {}:{}:{}",
+            self.path.display(),
+            self.line,
+            self.column
+        )?;
+        writeln!(f, "{gutter} |")?;
+        writeln!(f, "{line_number} | {}", self.snippet)?;
+        write!(f, "{gutter} | {caret_indent}^")
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::ParseError;
+
+    #[test]
+    fn test_parse_error_location() {
+        let source = ".foo {\n\tcolor: red;\n\t.bar { \"unterminated }\n}";
+        let offset = source.find('"').unwrap();
+        let error = ParseError::new(
+            Path::new("style.module.css"),
+            source,
+            offset,
+            "unterminated string".into(),
+        );
+
+        assert_eq!(error.line, 3);
+        assert_eq!(error.column, 9);
+        assert_eq!(error.snippet, "\t.bar { \"unterminated }");
+        assert_eq!(
+            error.to_string(),
+            [
+                "unterminated string",
+                " --> style.module.css:3:9",
+                "  |",
+                "3 | \t.bar { \"unterminated }",
+                "  | \t       ^",
+            ]
+            .join("\n")
+        );
+
+        // Offsets inside of a character point to its start.
+        let error = ParseError::new(Path::new("style.module.css"), "/* é */ }", 4, "".into());
+        assert_eq!(error.column, 4);
+    }
+}
@@ -0,0 +1,79 @@
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// A list of glob patterns, like the `include` and `exclude` settings.
+///
+/// Patterns are matched against paths with `/` separators. `*` doesn't match
+/// separators, use `**` to match any number of folders.
+#[derive(Debug, Clone)]
+pub struct GlobList {
+    patterns: Vec<String>,
+    set: GlobSet,
+}
+
+impl Default for GlobList {
+    fn default() -> Self {
+        Self {
+            patterns: Vec::new(),
+            set: GlobSet::empty(),
+        }
+    }
+}
+
+impl GlobList {
+    pub fn new(patterns: Vec<String>) -> anyhow::Result<Self> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &patterns {
+            builder.add(
+                GlobBuilder::new(pattern.trim_start_matches("./"))
+                    .literal_separator(true)
+                    .build()?,
+            );
+        }
+
+        Ok(Self {
+            set: builder.build()?,
+            patterns,
+        })
+    }
+
+    pub fn is_match(&self, path: &str) -> bool {
+        self.set.is_match(path)
+    }
+
+    /// Returns the first pattern that matches the path.
+    pub fn first_match(&self, path: &str) -> Option<&str> {
+        self.set
+            .matches(path)
+            .first()
+            .map(|i| self.patterns[*i].as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_glob_list() {
+        let globs = GlobList::new(vec![
+            "src/legacy/**".into(),
+            "./vendor/*.css".into(),
+            "**/*.styles.css".into(),
+        ])
+        .unwrap();
+
+        assert!(globs.is_match("src/legacy/a.module.css"));
+        assert!(globs.is_match("src/legacy/nested/b.module.css"));
+        assert!(!globs.is_match("src/legacy.module.css"));
+        assert!(globs.is_match("vendor/a.css"));
+        assert!(!globs.is_match("vendor/nested/a.css"));
+        assert!(globs.is_match("src/card.styles.css"));
+        assert_eq!(
+            globs.first_match("src/legacy/card.styles.css"),
+            Some("src/legacy/**")
+        );
+        assert_eq!(globs.first_match("src/card.module.css"), None);
+
+        assert!(GlobList::new(vec!["src/[".into()]).is_err());
+    }
+}
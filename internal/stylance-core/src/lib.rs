@@ -1,122 +1,1078 @@
 mod class_name_pattern;
+#[cfg(feature = "codegen")]
+pub mod codegen;
 mod config;
+mod error;
+mod glob;
+mod minify;
 mod parse;
 pub mod path_utils;
+#[cfg(feature = "walk")]
+mod walk;
 
 use std::{
     borrow::Cow,
+    collections::{HashMap, HashSet},
     fs,
     hash::{Hash as _, Hasher as _},
     path::{Path, PathBuf},
 };
 
-use anyhow::anyhow;
-use parse::{CssFragment, Global};
+use anyhow::{anyhow, bail, Context as _};
+use parse::{Composes, ComposesFrom, CssFragment, Export, Global};
+use serde::Serialize;
 use siphasher::sip::SipHasher13;
 
-pub use crate::config::{Config, PartialConfig};
-use crate::path_utils::{diff_normalized_paths, normalize};
+pub use crate::config::{Config, HashStrategy, PartialConfig, Scope};
+use crate::error::line_column;
+pub use crate::error::ParseError;
+pub use crate::glob::GlobList;
+pub use crate::minify::MinifiedNames;
+#[cfg(feature = "walk")]
+pub use crate::walk::{find_css_modules, walk_files, IgnoreMatcher};
+use crate::{
+    class_name_pattern::{escape_identifier, PatternContext},
+    path_utils::{diff_normalized_paths, normalize},
+};
 
-pub fn hash_path(input: &Path) -> u64 {
-    let normalized_separators = input
+fn normalize_separators(input: &Path) -> String {
+    input
         .components()
         .map(|c| c.as_os_str().to_string_lossy())
         .collect::<Vec<_>>()
-        .join("/");
+        .join("/")
+}
+
+/// Line endings are normalized so that checkouts with different git autocrlf
+/// settings produce the same hash.
+fn normalize_line_endings(input: &str) -> Cow<'_, str> {
+    if input.contains("\r\n") {
+        Cow::Owned(input.replace("\r\n", "\n"))
+    } else {
+        Cow::Borrowed(input)
+    }
+}
+
+pub fn hash_path(input: &Path) -> u64 {
+    let mut hasher = SipHasher13::new();
+    normalize_separators(input).hash(&mut hasher);
+    hasher.finish()
+}
+
+pub fn hash_contents(contents: &str) -> u64 {
+    let mut hasher = SipHasher13::new();
+    normalize_line_endings(contents).hash(&mut hasher);
+    hasher.finish()
+}
 
+pub fn hash_path_and_contents(input: &Path, contents: &str) -> u64 {
     let mut hasher = SipHasher13::new();
-    normalized_separators.hash(&mut hasher);
+    normalize_separators(input).hash(&mut hasher);
+    normalize_line_endings(contents).hash(&mut hasher);
     hasher.finish()
 }
 
+#[derive(Clone, Serialize)]
 pub struct Class {
     pub original_name: String,
     pub hashed_name: String,
+    /// Hashed (or global) names of the classes composed by this class through
+    /// `composes` declarations, including transitive compositions.
+    pub composes: Vec<String>,
+}
+
+/// A scoped identifier other than a class, such as a keyframes or custom property name.
+pub struct ScopedName {
+    pub original_name: String,
+    pub hashed_name: String,
+}
+
+/// Where a class is first declared in a css module.
+pub struct ClassLocation {
+    pub name: String,
+    /// 1 based line number.
+    pub line: usize,
+    /// 1 based column number, counted in characters.
+    pub column: usize,
+}
+
+/// A `name: value` pair declared in an ICSS `:export { ... }` block.
+pub struct ExportedValue {
+    pub name: String,
+    pub value: String,
+}
+
+pub struct GetClassesResult {
+    pub hash: String,
+    pub classes: Vec<Class>,
+    pub keyframes: Vec<ScopedName>,
+    /// Scoped custom properties, only populated when `scoped_custom_properties` is enabled.
+    /// The hashed names include the leading `--`.
+    pub vars: Vec<ScopedName>,
+    /// Values declared in `:export { ... }` blocks.
+    pub exports: Vec<ExportedValue>,
+    /// Other css files that were read to resolve `composes ... from "file"` declarations.
+    pub dependencies: Vec<PathBuf>,
 }
 
-fn make_hash(relative_path: &Path, hash_len: usize) -> anyhow::Result<String> {
-    let hash = hash_path(relative_path);
-    let mut hash_str = format!("{hash:x}");
-    hash_str.truncate(hash_len);
-    Ok(hash_str)
+fn make_hash(relative_path: &Path, contents: &str, config: &Config) -> String {
+    let hash = match config.hash_strategy {
+        HashStrategy::Path => hash_path(relative_path),
+        HashStrategy::Content => hash_contents(contents),
+        HashStrategy::PathAndContent => hash_path_and_contents(relative_path, contents),
+    };
+    format!("{hash:x}")
 }
 
+/// Computes the values of the class name pattern placeholders for a css module.
+fn make_pattern_context(relative_path: &Path, contents: &str, config: &Config) -> PatternContext {
+    let full_hash = make_hash(relative_path, contents, config);
+    let mut hash = full_hash.clone();
+    hash.truncate(config.hash_len);
+
+    let file = relative_path
+        .file_stem()
+        .map(|s| s.to_string_lossy())
+        .unwrap_or_default();
+    let dir = relative_path
+        .parent()
+        .and_then(|p| p.file_name())
+        .map(|s| s.to_string_lossy())
+        .unwrap_or_default();
+    let crate_name = match &config.crate_name {
+        Some(name) => Cow::Borrowed(name.as_str()),
+        None => config
+            .manifest_dir
+            .file_name()
+            .map(|s| s.to_string_lossy())
+            .unwrap_or_default(),
+    };
+
+    PatternContext {
+        hash,
+        full_hash,
+        file: PatternContext::sanitize(&file),
+        dir: PatternContext::sanitize(&dir),
+        crate_name: PatternContext::sanitize(&crate_name),
+        path: PatternContext::sanitize(&normalize_separators(&relative_path.with_extension(""))),
+    }
+}
+
+/// Produces the scoped names of a css module, either from the class name pattern
+/// or from the minified names when `minify_names` is enabled.
+struct ModuleNames<'a> {
+    config: &'a Config,
+    /// Relative path of the module with `/` separators, used as key in the minified names.
+    module: String,
+    pattern_context: PatternContext,
+}
+
+impl<'a> ModuleNames<'a> {
+    fn new(relative_path: &Path, contents: &str, config: &'a Config) -> Self {
+        Self {
+            config,
+            module: normalize_separators(relative_path),
+            pattern_context: make_pattern_context(relative_path, contents, config),
+        }
+    }
+
+    fn scoped(&self, name: &str) -> anyhow::Result<String> {
+        if !self.config.minify_names {
+            return Ok(self
+                .config
+                .class_name_pattern
+                .apply(name, &self.pattern_context));
+        }
+
+        self.config
+            .minified_names
+            .get(&self.module, name)
+            .map(|s| s.to_owned())
+            .ok_or_else(|| {
+                anyhow!(
+                    "`{name}` in {} has no minified name in {}, run the stylance cli to update it",
+                    self.module,
+                    self.config.minified_names_file.to_string_lossy()
+                )
+            })
+    }
+}
+
+fn parse_css_file<'s>(
+    css_file: &Path,
+    contents: &'s str,
+    config: &Config,
+) -> Result<Vec<CssFragment<'s>>, ParseError> {
+    parse::parse_css(contents, config.default_scope).map_err(|e| {
+        let message = e.inner().to_string();
+        let message = if message.is_empty() {
+            "invalid css syntax".to_owned()
+        } else {
+            message
+        };
+        ParseError::new(css_file, contents, e.offset(), message)
+    })
+}
+
+/// Links a position of the modified css to the position it came from in the original file.
+///
+/// The position is unchanged until the next mapping.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceMapping {
+    /// Byte offset in the modified contents.
+    pub generated: usize,
+    /// Byte offset in the original contents.
+    pub original: usize,
+    /// Original name of a scoped identifier that was replaced at this position.
+    pub name: Option<String>,
+}
+
+#[derive(Clone)]
 pub struct ModifyCssResult {
     pub path: PathBuf,
     pub relative_path: PathBuf,
     pub hash: String,
     pub contents: String,
+    pub original_contents: String,
+    /// Mappings from `contents` to `original_contents`, sorted by position.
+    /// There is one at the start of every line of `contents`.
+    pub mappings: Vec<SourceMapping>,
+    /// Scoped classes of the module, the same ones returned by [`get_classes`].
+    pub classes: Vec<Class>,
+    /// Other css files that were read to resolve `composes ... from "file"` declarations.
+    pub dependencies: Vec<PathBuf>,
+}
+
+/// What a fragment of the original css is replaced with.
+enum Replace<'s> {
+    /// A scoped name that replaces the original identifier.
+    Scoped(String),
+    /// A part of the fragment that is kept as is, like the contents of `:global(...)`.
+    Unwrap(&'s str),
+    Remove,
+}
+
+/// Builds the modified css along with its mappings to the original file.
+struct ModifiedCss<'s> {
+    source: &'s str,
+    contents: String,
+    mappings: Vec<SourceMapping>,
+}
+
+impl ModifiedCss<'_> {
+    fn original_offset(&self, span: &str) -> usize {
+        span.as_ptr() as usize - self.source.as_ptr() as usize
+    }
+
+    /// Appends a span of the original css unchanged, with a mapping at the start of each line.
+    fn copy(&mut self, span: &str) {
+        let original = self.original_offset(span);
+        let line_starts = std::iter::once(0).chain(span.match_indices('\n').map(|(i, _)| i + 1));
+        for line_start in line_starts.filter(|i| *i < span.len()) {
+            self.mappings.push(SourceMapping {
+                generated: self.contents.len() + line_start,
+                original: original + line_start,
+                name: None,
+            });
+        }
+        self.contents.push_str(span);
+    }
+
+    /// Appends a replacement for the identifier at `span`.
+    fn replace(&mut self, span: &str, replacement: &str) {
+        self.mappings.push(SourceMapping {
+            generated: self.contents.len(),
+            original: self.original_offset(span),
+            name: Some(span.to_owned()),
+        });
+        self.contents.push_str(replacement);
+    }
+}
+
+impl ModifyCssResult {
+    /// Maps a byte offset of `contents` back to `original_contents`.
+    pub fn original_offset(&self, generated: usize) -> usize {
+        let index = self.mappings.partition_point(|m| m.generated <= generated);
+        let Some(mapping) = index.checked_sub(1).map(|i| &self.mappings[i]) else {
+            return 0;
+        };
+
+        match mapping.name {
+            // Scoped names have a different length than the original, map to their start.
+            Some(_) => mapping.original,
+            None => {
+                (mapping.original + generated - mapping.generated).min(self.original_contents.len())
+            }
+        }
+    }
 }
 
 pub fn load_and_modify_css(css_file: &Path, config: &Config) -> anyhow::Result<ModifyCssResult> {
     let css_file = normalize(css_file)?;
     let hash_root = normalize(&config.hash_root_path)?;
     let relative_path = diff_normalized_paths(&css_file, &hash_root)?;
-    let hash_str = make_hash(&relative_path, config.hash_len)?;
 
     let css_file_contents = fs::read_to_string(&css_file)?;
+    let names = ModuleNames::new(&relative_path, &css_file_contents, config);
 
-    let fragments = parse::parse_css(&css_file_contents).map_err(|e| anyhow!("{e}"))?;
+    let fragments = parse_css_file(&css_file, &css_file_contents, config)?;
 
-    let mut new_file = String::with_capacity(css_file_contents.len() * 2);
+    // Animation names and var() references are only scoped if they refer to
+    // keyframes or custom properties defined in this module.
+    let keyframes = fragments
+        .iter()
+        .filter_map(|f| match f {
+            CssFragment::Keyframes(name) => Some(*name),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    let custom_properties = fragments
+        .iter()
+        .filter_map(|f| match f {
+            CssFragment::CustomProperty(name) if config.scoped_custom_properties => Some(*name),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+
+    let mut output = ModifiedCss {
+        source: &css_file_contents,
+        contents: String::with_capacity(css_file_contents.len() * 2),
+        mappings: Vec::new(),
+    };
     let mut cursor = css_file_contents.as_str();
 
-    for fragment in fragments {
-        let (span, replace) = match fragment {
-            CssFragment::Class(class) => (
-                class,
-                Cow::Owned(config.class_name_pattern.apply(class, &hash_str)),
+    for fragment in &fragments {
+        let (span, replace) = match *fragment {
+            CssFragment::Class(name) | CssFragment::Keyframes(name) => (
+                name,
+                Replace::Scoped(escape_identifier(&names.scoped(name)?).into_owned()),
             ),
-            CssFragment::Global(Global { inner, outer }) => (outer, Cow::Borrowed(inner)),
+            CssFragment::AnimationName(name) if keyframes.contains(name) => (
+                name,
+                Replace::Scoped(escape_identifier(&names.scoped(name)?).into_owned()),
+            ),
+            CssFragment::CustomProperty(name) | CssFragment::VarReference(name)
+                if custom_properties.contains(name) =>
+            {
+                (name, Replace::Scoped(names.scoped(name)?))
+            }
+            CssFragment::GlobalClass(_)
+            | CssFragment::AnimationName(_)
+            | CssFragment::CustomProperty(_)
+            | CssFragment::VarReference(_) => continue,
+            CssFragment::Global(Global { inner, outer }) => (outer, Replace::Unwrap(inner)),
+            CssFragment::Composes(Composes { span, .. })
+            | CssFragment::Export(Export { span, .. })
+            | CssFragment::Remove(span) => (span, Replace::Remove),
         };
 
         let (before, after) = cursor.split_at(span.as_ptr() as usize - cursor.as_ptr() as usize);
         cursor = &after[span.len()..];
-        new_file.push_str(before);
-        new_file.push_str(&replace);
+        output.copy(before);
+        match replace {
+            Replace::Scoped(scoped) => output.replace(span, &scoped),
+            Replace::Unwrap(inner) => output.copy(inner),
+            Replace::Remove => {}
+        }
     }
 
-    new_file.push_str(cursor);
+    output.copy(cursor);
+    let ModifiedCss {
+        contents: new_file,
+        mappings,
+        ..
+    } = output;
+
+    let GetClassesResult {
+        classes,
+        dependencies,
+        ..
+    } = module_classes(&css_file, fragments, &names, &mut Vec::new())?;
 
     Ok(ModifyCssResult {
         path: css_file,
         relative_path,
-        hash: hash_str,
+        hash: names.pattern_context.hash,
         contents: new_file,
+        original_contents: css_file_contents,
+        mappings,
+        classes,
+        dependencies,
     })
 }
 
-pub fn get_classes(css_file: &Path, config: &Config) -> anyhow::Result<(String, Vec<Class>)> {
+pub fn get_classes(css_file: &Path, config: &Config) -> anyhow::Result<GetClassesResult> {
+    if let Some(pattern) = config.excluded_by(css_file) {
+        bail!(
+            "{} is excluded from stylance by the exclude pattern \"{pattern}\"",
+            css_file.to_string_lossy()
+        );
+    }
+
+    let mut result = get_classes_impl(css_file, config, &mut Vec::new())?;
+    if config.minify_names {
+        result.dependencies.push(config.minified_names_file.clone());
+    }
+    Ok(result)
+}
+
+/// Finds where each scoped class of a css module is first declared.
+pub fn class_locations(css_file: &Path, config: &Config) -> anyhow::Result<Vec<ClassLocation>> {
+    let css_file = normalize(css_file)?;
+    let css_file_contents = fs::read_to_string(&css_file)
+        .with_context(|| format!("Failed to read {}", css_file.to_string_lossy()))?;
+
+    let mut locations = Vec::<ClassLocation>::new();
+    for fragment in parse_css_file(&css_file, &css_file_contents, config)? {
+        if let CssFragment::Class(name) = fragment {
+            if locations.iter().any(|l| l.name == name) {
+                continue;
+            }
+            let offset = name.as_ptr() as usize - css_file_contents.as_ptr() as usize;
+            let (line, column) = line_column(&css_file_contents, offset);
+            locations.push(ClassLocation {
+                name: name.to_owned(),
+                line,
+                column,
+            });
+        }
+    }
+
+    Ok(locations)
+}
+
+/// Finds the class names a css module leaves unscoped, minified names must not collide
+/// with them.
+pub fn global_class_names(css_file: &Path, config: &Config) -> anyhow::Result<Vec<String>> {
+    let css_file = normalize(css_file)?;
+    let css_file_contents = fs::read_to_string(&css_file)
+        .with_context(|| format!("Failed to read {}", css_file.to_string_lossy()))?;
+
+    let mut names = Vec::new();
+    for fragment in parse_css_file(&css_file, &css_file_contents, config)? {
+        match fragment {
+            CssFragment::GlobalClass(name) => names.push(name),
+            CssFragment::Global(Global { inner, .. }) => {
+                names.extend(parse::selector_classes(inner))
+            }
+            CssFragment::Composes(Composes {
+                names: composes,
+                from: ComposesFrom::Global,
+                ..
+            }) => names.extend(composes),
+            _ => {}
+        }
+    }
+
+    Ok(names.into_iter().map(str::to_owned).collect())
+}
+
+/// Allocates the minified names of the scoped names declared in a css module.
+pub fn allocate_minified_names(
+    css_file: &Path,
+    config: &Config,
+    minified_names: &mut MinifiedNames,
+) -> anyhow::Result<()> {
+    let css_file = normalize(css_file)?;
+    let hash_root = normalize(&config.hash_root_path)?;
+    let module = normalize_separators(&diff_normalized_paths(&css_file, &hash_root)?);
+
+    let css_file_contents = fs::read_to_string(&css_file)
+        .with_context(|| format!("Failed to read {}", css_file.to_string_lossy()))?;
+
+    for fragment in parse_css_file(&css_file, &css_file_contents, config)? {
+        match fragment {
+            CssFragment::Class(name) | CssFragment::Keyframes(name) => {
+                minified_names.get_or_allocate(&module, name);
+            }
+            CssFragment::CustomProperty(name) if config.scoped_custom_properties => {
+                minified_names.get_or_allocate(&module, name);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn get_classes_impl(
+    css_file: &Path,
+    config: &Config,
+    import_stack: &mut Vec<PathBuf>,
+) -> anyhow::Result<GetClassesResult> {
     let css_file = normalize(css_file)?;
     let hash_root = normalize(&config.hash_root_path)?;
     let relative_path = diff_normalized_paths(&css_file, &hash_root)?;
-    let hash_str = make_hash(&relative_path, config.hash_len)?;
-
-    let css_file_contents = fs::read_to_string(css_file)?;
-
-    let mut classes = parse::parse_css(&css_file_contents)
-        .map_err(|e| anyhow!("{e}"))?
-        .into_iter()
-        .filter_map(|c| {
-            if let CssFragment::Class(c) = c {
-                Some(c)
-            } else {
-                None
+
+    if import_stack.contains(&css_file) {
+        bail!(
+            "Cyclic composes import involving {}",
+            css_file.to_string_lossy()
+        );
+    }
+
+    let css_file_contents = fs::read_to_string(&css_file)
+        .with_context(|| format!("Failed to read {}", css_file.to_string_lossy()))?;
+    let names = ModuleNames::new(&relative_path, &css_file_contents, config);
+    let fragments = parse_css_file(&css_file, &css_file_contents, config)?;
+
+    module_classes(&css_file, fragments, &names, import_stack)
+}
+
+/// Collects the scoped names and exports of a parsed css module.
+fn module_classes(
+    css_file: &Path,
+    fragments: Vec<CssFragment>,
+    names: &ModuleNames,
+    import_stack: &mut Vec<PathBuf>,
+) -> anyhow::Result<GetClassesResult> {
+    let config = names.config;
+
+    let mut classes = Vec::new();
+    let mut composes = Vec::new();
+    let mut keyframes = Vec::new();
+    let mut vars = Vec::new();
+    let mut exports = Vec::<ExportedValue>::new();
+    for fragment in fragments {
+        match fragment {
+            CssFragment::Class(c) => classes.push(c),
+            CssFragment::Composes(c) => composes.push(c),
+            CssFragment::Keyframes(k) => keyframes.push(k),
+            CssFragment::CustomProperty(v) if config.scoped_custom_properties => vars.push(v),
+            CssFragment::Export(Export { values, .. }) => {
+                for (name, value) in values {
+                    // Later declarations override earlier ones
+                    exports.retain(|e| e.name != name);
+                    exports.push(ExportedValue {
+                        name: name.to_owned(),
+                        value: value.to_owned(),
+                    });
+                }
             }
-        })
-        .collect::<Vec<_>>();
+            CssFragment::Global(_)
+            | CssFragment::GlobalClass(_)
+            | CssFragment::AnimationName(_)
+            | CssFragment::CustomProperty(_)
+            | CssFragment::VarReference(_)
+            | CssFragment::Remove(_) => {}
+        }
+    }
 
     classes.sort();
     classes.dedup();
+    keyframes.sort();
+    keyframes.dedup();
+    vars.sort();
+    vars.dedup();
+
+    let mut dependencies = Vec::new();
+    import_stack.push(css_file.to_owned());
+    let composition = resolve_composes(
+        css_file,
+        &classes,
+        composes,
+        names,
+        import_stack,
+        &mut dependencies,
+    );
+    import_stack.pop();
+    let mut composition = composition?;
 
-    Ok((
-        hash_str.clone(),
-        classes
+    dependencies.sort();
+    dependencies.dedup();
+
+    Ok(GetClassesResult {
+        classes: classes
+            .into_iter()
+            .map(|class| {
+                Ok(Class {
+                    original_name: class.to_owned(),
+                    hashed_name: names.scoped(class)?,
+                    composes: composition.remove(class).unwrap_or_default(),
+                })
+            })
+            .collect::<anyhow::Result<_>>()?,
+        keyframes: keyframes
             .into_iter()
-            .map(|class| Class {
-                original_name: class.to_owned(),
-                hashed_name: config.class_name_pattern.apply(class, &hash_str),
+            .map(|name| {
+                Ok(ScopedName {
+                    original_name: name.to_owned(),
+                    hashed_name: names.scoped(name)?,
+                })
             })
-            .collect(),
-    ))
+            .collect::<anyhow::Result<_>>()?,
+        vars: vars
+            .into_iter()
+            .map(|name| {
+                Ok(ScopedName {
+                    original_name: name.to_owned(),
+                    hashed_name: format!("--{}", names.scoped(name)?),
+                })
+            })
+            .collect::<anyhow::Result<_>>()?,
+        exports,
+        hash: names.pattern_context.hash.clone(),
+        dependencies,
+    })
+}
+
+#[derive(Clone)]
+enum Composed<'s> {
+    /// A class declared in the same file, its own compositions must be followed.
+    Local(&'s str),
+    /// Names that are already fully resolved (global or from another file).
+    Resolved(Vec<String>),
+}
+
+/// Resolves the `composes` declarations of a css file.
+///
+/// Returns a map from class name to the list of names it composes. Other files
+/// that were read in the process are appended to `dependencies`.
+fn resolve_composes<'s>(
+    css_file: &Path,
+    classes: &[&'s str],
+    composes: Vec<Composes<'s>>,
+    module_names: &ModuleNames,
+    import_stack: &mut Vec<PathBuf>,
+    dependencies: &mut Vec<PathBuf>,
+) -> anyhow::Result<HashMap<&'s str, Vec<String>>> {
+    let mut direct = HashMap::<&str, Vec<Composed>>::new();
+    let mut imported = HashMap::<PathBuf, GetClassesResult>::new();
+
+    for Composes {
+        owners,
+        names,
+        from,
+        span,
+    } in composes
+    {
+        let owners = owners.filter(|o| !o.is_empty()).ok_or_else(|| {
+            anyhow!("`{span}`: composes can only be used inside a rule with a class selector")
+        })?;
+
+        let composed = match from {
+            ComposesFrom::Local => names
+                .iter()
+                .map(|name| {
+                    if !classes.contains(name) {
+                        bail!("`{span}`: class `{name}` is not defined in this file");
+                    }
+                    Ok(Composed::Local(name))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?,
+            ComposesFrom::Global => names
+                .iter()
+                .map(|name| Composed::Resolved(vec![name.to_string()]))
+                .collect(),
+            ComposesFrom::File(path) => {
+                let dep_path = normalize(
+                    css_file
+                        .parent()
+                        .expect("css file path should have a parent dir")
+                        .join(path),
+                )?;
+
+                if !imported.contains_key(&dep_path) {
+                    let result = get_classes_impl(&dep_path, module_names.config, import_stack)
+                        .with_context(|| format!("Failed to resolve `{span}`"))?;
+                    dependencies.push(dep_path.clone());
+                    dependencies.extend(result.dependencies.iter().cloned());
+                    imported.insert(dep_path.clone(), result);
+                }
+                let dep = &imported[&dep_path];
+
+                names
+                    .iter()
+                    .map(|name| {
+                        let class = dep
+                            .classes
+                            .iter()
+                            .find(|c| c.original_name == *name)
+                            .ok_or_else(|| {
+                                anyhow!("`{span}`: class `{name}` is not defined in {path}")
+                            })?;
+                        let mut resolved = vec![class.hashed_name.clone()];
+                        resolved.extend(class.composes.iter().cloned());
+                        Ok(Composed::Resolved(resolved))
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?
+            }
+        };
+
+        for owner in owners {
+            direct
+                .entry(owner)
+                .or_default()
+                .extend(composed.iter().cloned());
+        }
+    }
+
+    let mut composition = HashMap::new();
+    for &class in direct.keys() {
+        let mut names = Vec::new();
+        flatten_composes(class, &direct, module_names, &mut Vec::new(), &mut names)?;
+
+        let own_name = module_names.scoped(class)?;
+        let mut deduped = Vec::<String>::with_capacity(names.len());
+        for name in names {
+            if name != own_name && !deduped.contains(&name) {
+                deduped.push(name);
+            }
+        }
+        composition.insert(class, deduped);
+    }
+
+    Ok(composition)
+}
+
+fn flatten_composes<'s>(
+    class: &'s str,
+    direct: &HashMap<&'s str, Vec<Composed<'s>>>,
+    module_names: &ModuleNames,
+    visiting: &mut Vec<&'s str>,
+    out: &mut Vec<String>,
+) -> anyhow::Result<()> {
+    if visiting.contains(&class) {
+        bail!("Cyclic composes involving class `{class}`");
+    }
+
+    let Some(items) = direct.get(class) else {
+        return Ok(());
+    };
+
+    visiting.push(class);
+    for item in items {
+        match item {
+            Composed::Local(name) => {
+                out.push(module_names.scoped(name)?);
+                flatten_composes(name, direct, module_names, visiting, out)?;
+            }
+            Composed::Resolved(names) => out.extend(names.iter().cloned()),
+        }
+    }
+    visiting.pop();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scoped_custom_properties() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let css_path = dir.path().join("style.module.css");
+        fs::write(
+            &css_path,
+            ".card { --card-bg: red; color: var(--card-bg); border-color: var(--global-color); }",
+        )
+        .expect("write css file");
+
+        let config = |scoped_custom_properties| {
+            Config::from_partials(
+                dir.path().to_path_buf(),
+                PartialConfig {
+                    scoped_custom_properties: Some(scoped_custom_properties),
+                    ..Default::default()
+                },
+                None,
+            )
+            .expect("config should be valid")
+        };
+
+        let result = load_and_modify_css(&css_path, &config(false)).expect("should transform");
+        let hash = result.hash;
+        assert_eq!(
+            result.contents,
+            format!(
+                ".card-{hash} {{ --card-bg: red; color: var(--card-bg); border-color: var(--global-color); }}"
+            )
+        );
+
+        let result = load_and_modify_css(&css_path, &config(true)).expect("should transform");
+        assert_eq!(
+            result.contents,
+            format!(
+                ".card-{hash} {{ --card-bg-{hash}: red; color: var(--card-bg-{hash}); border-color: var(--global-color); }}"
+            )
+        );
+
+        let result = get_classes(&css_path, &config(true)).expect("should get classes");
+        assert_eq!(result.vars.len(), 1);
+        assert_eq!(result.vars[0].original_name, "card-bg");
+        assert_eq!(result.vars[0].hashed_name, format!("--card-bg-{hash}"));
+
+        let result = get_classes(&css_path, &config(false)).expect("should get classes");
+        assert!(result.vars.is_empty());
+    }
+
+    #[test]
+    fn test_default_scope_global() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let css_path = dir.path().join("style.module.css");
+        fs::write(
+            &css_path,
+            ".a { color: red; }\n:local {\n.b { color: red; }\n}\n.c :local(.d) { color: red; }",
+        )
+        .expect("write css file");
+
+        let config = Config::from_partials(
+            dir.path().to_path_buf(),
+            PartialConfig {
+                default_scope: Some(Scope::Global),
+                ..Default::default()
+            },
+            None,
+        )
+        .expect("config should be valid");
+
+        let result = load_and_modify_css(&css_path, &config).expect("should transform");
+        let hash = result.hash;
+        assert_eq!(
+            result.contents,
+            format!(".a {{ color: red; }}\n\n.b-{hash} {{ color: red; }}\n\n.c .d-{hash} {{ color: red; }}")
+        );
+
+        let result = get_classes(&css_path, &config).expect("should get classes");
+        let classes = result
+            .classes
+            .iter()
+            .map(|c| c.original_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(classes, ["b", "d"]);
+    }
+
+    #[test]
+    fn test_global_block_animation() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let css_path = dir.path().join("style.module.css");
+        fs::write(
+            &css_path,
+            "@keyframes spin { to { opacity: 0; } }\n.a { animation: spin 1s; }\n:global {\n.b { animation: spin 1s; }\n}",
+        )
+        .expect("write css file");
+
+        let config = Config::from_partials(dir.path().to_path_buf(), Default::default(), None)
+            .expect("config should be valid");
+
+        // The global block references the unscoped `spin`, not the local keyframes.
+        let result = load_and_modify_css(&css_path, &config).expect("should transform");
+        let hash = result.hash;
+        assert_eq!(
+            result.contents,
+            format!(
+                "@keyframes spin-{hash} {{ to {{ opacity: 0; }} }}\n.a-{hash} {{ animation: spin-{hash} 1s; }}\n\n.b {{ animation: spin 1s; }}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_error_location() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let css_path = dir.path().join("style.module.css");
+        fs::write(&css_path, ".a {\n  color: red;\n}\n}\n").expect("write css file");
+
+        let config = Config::from_partials(dir.path().to_path_buf(), Default::default(), None)
+            .expect("config should be valid");
+
+        let Err(err) = get_classes(&css_path, &config) else {
+            panic!("parsing should fail");
+        };
+        let err = err
+            .downcast::<ParseError>()
+            .expect("error should be a ParseError");
+
+        assert_eq!(err.path, normalize(&css_path).unwrap());
+        assert_eq!((err.line, err.column), (4, 1));
+        assert_eq!(err.snippet, "}");
+    }
+
+    #[test]
+    fn test_unclosed_scope_block_error() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let css_path = dir.path().join("style.module.css");
+        fs::write(&css_path, ":global {\n  .a { color: red; }\n").expect("write css file");
+
+        let config = Config::from_partials(dir.path().to_path_buf(), Default::default(), None)
+            .expect("config should be valid");
+
+        let Err(err) = get_classes(&css_path, &config) else {
+            panic!("parsing should fail");
+        };
+        let err = err
+            .downcast::<ParseError>()
+            .expect("error should be a ParseError");
+
+        assert_eq!((err.line, err.column), (3, 1));
+        assert!(err.message.contains("`}`"), "{}", err.message);
+    }
+
+    #[test]
+    fn test_hash_strategy() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        fs::create_dir(dir.path().join("moved")).expect("create dir");
+        let css_a = dir.path().join("style.module.css");
+        let css_b = dir.path().join("moved/style.module.css");
+        fs::write(&css_a, ".a { color: red; }\n").expect("write css file");
+        fs::write(&css_b, ".a { color: red; }\r\n").expect("write css file");
+
+        let config = |hash_strategy| {
+            Config::from_partials(
+                dir.path().to_path_buf(),
+                PartialConfig {
+                    hash_strategy: Some(hash_strategy),
+                    ..Default::default()
+                },
+                None,
+            )
+            .expect("config should be valid")
+        };
+        let hash = |css_file: &Path, config: &Config| {
+            let modified = load_and_modify_css(css_file, config).expect("should transform");
+            let classes = get_classes(css_file, config).expect("should get classes");
+            assert_eq!(modified.hash, classes.hash);
+            modified.hash
+        };
+
+        let path = config(HashStrategy::Path);
+        assert_ne!(hash(&css_a, &path), hash(&css_b, &path));
+
+        let content = config(HashStrategy::Content);
+        assert_eq!(hash(&css_a, &content), hash(&css_b, &content));
+        assert_ne!(hash(&css_a, &content), hash(&css_a, &path));
+
+        let path_and_content = config(HashStrategy::PathAndContent);
+        assert_ne!(
+            hash(&css_a, &path_and_content),
+            hash(&css_b, &path_and_content)
+        );
+        assert_ne!(hash(&css_a, &path_and_content), hash(&css_a, &content));
+    }
+
+    #[test]
+    fn test_include_exclude() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let legacy = dir.path().join("src/legacy/style.module.css");
+        fs::create_dir_all(legacy.parent().unwrap()).expect("create dir");
+        fs::write(&legacy, ".a { color: red; }\n").expect("write css file");
+
+        let config = Config::from_partials(
+            dir.path().to_path_buf(),
+            PartialConfig {
+                include: Some(vec!["src/**/*.styles.css".into()]),
+                exclude: Some(vec!["src/legacy/**".into()]),
+                ..Default::default()
+            },
+            None,
+        )
+        .expect("config should be valid");
+
+        assert!(config.is_css_module(&dir.path().join("src/style.module.css")));
+        assert!(config.is_css_module(&dir.path().join("src/card/card.styles.css")));
+        assert!(!config.is_css_module(&dir.path().join("src/card/card.css")));
+        assert!(!config.is_css_module(&legacy));
+        assert!(!config.is_css_module(&dir.path().join("src/legacy/card.styles.css")));
+
+        let Err(err) = get_classes(&legacy, &config) else {
+            panic!("excluded files can't be imported");
+        };
+        assert!(
+            err.to_string()
+                .contains("exclude pattern \"src/legacy/**\""),
+            "error should name the pattern: {err}"
+        );
+
+        let invalid = Config::from_partials(
+            dir.path().to_path_buf(),
+            PartialConfig {
+                exclude: Some(vec!["src/[".into()]),
+                ..Default::default()
+            },
+            None,
+        );
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn test_class_name_pattern_validation() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let css_path = dir.path().join("style.module.css");
+        fs::write(&css_path, ".a { color: red; }\n").expect("write css file");
+
+        let config = |pattern: &str| {
+            Config::from_partials(
+                dir.path().to_path_buf(),
+                PartialConfig {
+                    class_name_pattern: Some(
+                        serde_json::from_str(&format!("\"{pattern}\"")).expect("should parse"),
+                    ),
+                    ..Default::default()
+                },
+                None,
+            )
+        };
+
+        let Err(err) = config("[name] [hash]") else {
+            panic!("whitespace should be rejected");
+        };
+        assert!(
+            err.to_string().contains("\"[name] [hash]\""),
+            "error should name the pattern: {err}"
+        );
+        assert!(
+            config("1-[name]").is_err(),
+            "leading digits should be rejected"
+        );
+
+        // Placeholders that may start with a digit are escaped in the css output.
+        let config = config("[hash]-[name]").expect("config should be valid");
+        let modified = load_and_modify_css(&css_path, &config).expect("should transform");
+        let hashed_name = get_classes(&css_path, &config)
+            .expect("should get classes")
+            .classes[0]
+            .hashed_name
+            .clone();
+        assert_eq!(
+            modified.contents,
+            format!(".{} {{ color: red; }}\n", escape_identifier(&hashed_name))
+        );
+    }
+
+    #[test]
+    fn test_source_mappings() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let css_path = dir.path().join("style.module.css");
+        let source = ".a, :global(.b) {\n  color: red;\n}\n";
+        fs::write(&css_path, source).expect("write css file");
+
+        let config = Config::from_partials(dir.path().to_path_buf(), Default::default(), None)
+            .expect("config should be valid");
+        let modified = load_and_modify_css(&css_path, &config).expect("should transform");
+
+        let hashed_a = format!("a-{}", modified.hash);
+        assert_eq!(
+            modified.contents,
+            format!(".{hashed_a}, .b {{\n  color: red;\n}}\n")
+        );
+
+        let mapping = |generated, original, name: Option<&str>| SourceMapping {
+            generated,
+            original,
+            name: name.map(|s| s.to_owned()),
+        };
+        let after_a = 1 + hashed_a.len();
+        assert_eq!(
+            modified.mappings,
+            [
+                mapping(0, 0, None),
+                mapping(1, 1, Some("a")),
+                mapping(after_a, 2, None),
+                mapping(after_a + 2, 12, None),
+                mapping(after_a + 4, 15, None),
+                mapping(after_a + 7, 18, None),
+                mapping(after_a + 21, 32, None),
+            ]
+        );
+    }
 }
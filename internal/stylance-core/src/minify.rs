@@ -0,0 +1,213 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+
+const HEADER: &str = concat!(
+    "# This file is generated by stylance, it maps the scoped names of each css module\n",
+    "# to their minified name. It should be checked into version control.\n\n",
+);
+
+const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// Names that are never allocated (in any case), ad blockers hide the elements using them.
+const BLOCKED: &[&str] = &["ad", "ads", "adv"];
+
+/// Short names assigned to the scoped names of each css module when `minify_names` is enabled.
+///
+/// Names are allocated by the stylance cli and persisted to a lock file so that the
+/// proc macros and the cli agree on them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MinifiedNames {
+    /// Module path relative to `hash_root_path` -> original name -> minified name.
+    modules: BTreeMap<String, BTreeMap<String, String>>,
+    #[serde(skip)]
+    used: HashSet<String>,
+    #[serde(skip)]
+    reserved: HashSet<String>,
+    #[serde(skip)]
+    next_index: usize,
+    #[serde(skip)]
+    modified: bool,
+}
+
+impl MinifiedNames {
+    /// Loads the names from a lock file, a missing file yields an empty set of names.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", path.to_string_lossy()))
+            }
+        };
+
+        let mut names: Self = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse {}", path.to_string_lossy()))?;
+        names.used = names
+            .modules
+            .values()
+            .flat_map(|m| m.values().cloned())
+            .collect();
+        Ok(names)
+    }
+
+    /// Writes the names to a lock file.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let contents = toml::to_string(self)?;
+        fs::write(path, format!("{HEADER}{contents}"))
+            .with_context(|| format!("Failed to write {}", path.to_string_lossy()))
+    }
+
+    pub fn get(&self, module: &str, name: &str) -> Option<&str> {
+        self.modules.get(module)?.get(name).map(|s| s.as_str())
+    }
+
+    /// Returns the minified name of `name` in `module`, allocating the next free name if needed.
+    pub fn get_or_allocate(&mut self, module: &str, name: &str) -> &str {
+        if self.get(module, name).is_none() {
+            let minified = loop {
+                let candidate = short_name(self.next_index);
+                self.next_index += 1;
+                if self.is_available(&candidate) {
+                    break candidate;
+                }
+            };
+
+            self.used.insert(minified.clone());
+            self.modules
+                .entry(module.to_owned())
+                .or_default()
+                .insert(name.to_owned(), minified);
+            self.modified = true;
+        }
+
+        &self.modules[module][name]
+    }
+
+    /// Prevents `name` from being allocated, such as a global class name of the crate.
+    ///
+    /// Scoped names that were already given `name` lose their minified name and have to
+    /// be allocated again.
+    pub fn reserve(&mut self, name: &str) {
+        if !self.reserved.insert(name.to_owned()) || !self.used.remove(name) {
+            return;
+        }
+
+        for names in self.modules.values_mut() {
+            names.retain(|_, minified| minified != name);
+        }
+        self.modules.retain(|_, names| !names.is_empty());
+        self.modified = true;
+    }
+
+    fn is_available(&self, name: &str) -> bool {
+        !self.used.contains(name)
+            && !self.reserved.contains(name)
+            && !BLOCKED.iter().any(|b| b.eq_ignore_ascii_case(name))
+    }
+
+    /// Whether names were allocated or released since the names were loaded.
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+}
+
+/// Two sets of names are equal when they assign the same minified names.
+impl PartialEq for MinifiedNames {
+    fn eq(&self, other: &Self) -> bool {
+        self.modules == other.modules
+    }
+}
+
+/// Maps an index to `a`, `b`, ... `Z`, `aa`, `ab`, ...
+fn short_name(mut index: usize) -> String {
+    let mut name = Vec::new();
+    loop {
+        name.push(ALPHABET[index % ALPHABET.len()]);
+        index /= ALPHABET.len();
+        if index == 0 {
+            break;
+        }
+        index -= 1;
+    }
+    name.reverse();
+    String::from_utf8(name).expect("alphabet is ascii")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_short_name() {
+        assert_eq!(short_name(0), "a");
+        assert_eq!(short_name(25), "z");
+        assert_eq!(short_name(26), "A");
+        assert_eq!(short_name(51), "Z");
+        assert_eq!(short_name(52), "aa");
+        assert_eq!(short_name(103), "aZ");
+        assert_eq!(short_name(104), "ba");
+    }
+
+    #[test]
+    fn test_minified_names_roundtrip() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let lock_path = dir.path().join("stylance.lock");
+
+        let mut names = MinifiedNames::load(&lock_path).expect("missing file should load");
+        assert_eq!(names.get_or_allocate("src/a.module.css", "header"), "a");
+        assert_eq!(names.get_or_allocate("src/a.module.css", "footer"), "b");
+        assert_eq!(names.get_or_allocate("src/b.module.css", "header"), "c");
+        assert_eq!(names.get_or_allocate("src/a.module.css", "header"), "a");
+        assert!(names.is_modified());
+        names.save(&lock_path).expect("should save");
+
+        let mut loaded = MinifiedNames::load(&lock_path).expect("should load");
+        assert!(!loaded.is_modified());
+        assert_eq!(loaded.get("src/b.module.css", "header"), Some("c"));
+        assert_eq!(loaded.get_or_allocate("src/b.module.css", "footer"), "d");
+    }
+
+    #[test]
+    fn test_blocked_names() {
+        let mut names = MinifiedNames::default();
+        let allocated = (0..3000)
+            .map(|i| {
+                names
+                    .get_or_allocate("src/a.module.css", &format!("c{i}"))
+                    .to_owned()
+            })
+            .collect::<HashSet<_>>();
+
+        assert_eq!(allocated.len(), 3000);
+        for blocked in ["ad", "Ad", "aD", "AD", "ads", "ADS", "adv"] {
+            assert!(!allocated.contains(blocked), "{blocked} was allocated");
+        }
+        assert!(allocated.contains("ae"));
+    }
+
+    #[test]
+    fn test_reserved_names() {
+        let mut names = MinifiedNames::default();
+        names.reserve("b");
+        assert_eq!(names.get_or_allocate("src/a.module.css", "header"), "a");
+        assert_eq!(names.get_or_allocate("src/a.module.css", "footer"), "c");
+
+        // Reserving an allocated name moves its scoped name to a new minified name.
+        let dir = tempfile::tempdir().expect("tempdir");
+        let lock_path = dir.path().join("stylance.lock");
+        names.save(&lock_path).expect("should save");
+
+        let mut loaded = MinifiedNames::load(&lock_path).expect("should load");
+        loaded.reserve("a");
+        assert!(loaded.is_modified());
+        assert_eq!(loaded.get("src/a.module.css", "header"), None);
+        assert_eq!(loaded.get("src/a.module.css", "footer"), Some("c"));
+        assert_eq!(loaded.get_or_allocate("src/a.module.css", "header"), "b");
+    }
+}
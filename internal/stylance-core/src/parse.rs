@@ -1,11 +1,13 @@
 use winnow::{
-    combinator::{alt, cut_err, delimited, opt, peek, preceded, repeat, terminated},
-    error::{ContextError, ParseError},
+    combinator::{alt, cut_err, delimited, not, opt, peek, preceded, repeat, terminated},
+    error::{ContextError, ParseError, StrContext, StrContextValue},
     stream::{AsChar, ContainsToken, Range},
-    token::{none_of, one_of, take_till, take_until, take_while},
+    token::{any, none_of, one_of, take_till, take_until, take_while},
     ModalParser, ModalResult, Parser,
 };
 
+use crate::config::Scope;
+
 /// ```text
 ///         v----v inner span
 /// :global(.class)
@@ -17,14 +19,97 @@ pub struct Global<'s> {
     pub outer: &'s str,
 }
 
+/// Where the class names listed in a `composes` declaration come from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComposesFrom<'s> {
+    /// `composes: a b;`
+    Local,
+    /// `composes: a b from global;`
+    Global,
+    /// `composes: a b from "./other.module.css";`
+    File(&'s str),
+}
+
+/// ```text
+///               v-----------v names
+/// .foo { composes: bar baz from "./other.module.css"; }
+///        ^------------------------------------------^ span
+/// ```
+#[derive(Debug, PartialEq)]
+pub struct Composes<'s> {
+    /// Classes of the selector of the rule containing the declaration.
+    /// This is `None` until the enclosing style rule has been parsed.
+    pub owners: Option<Vec<&'s str>>,
+    pub names: Vec<&'s str>,
+    pub from: ComposesFrom<'s>,
+    pub span: &'s str,
+}
+
+/// ICSS export block, its values are exposed to rust and the block is removed from the output.
+///
+/// ```text
+/// :export { breakpoint: 768px; primary: #f00; }
+/// ^-------------------------------------------^ span
+/// ```
+#[derive(Debug, PartialEq)]
+pub struct Export<'s> {
+    pub values: Vec<(&'s str, &'s str)>,
+    pub span: &'s str,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum CssFragment<'s> {
     Class(&'s str),
+    /// Class name of a selector in global scope, it is left as is.
+    GlobalClass(&'s str),
     Global(Global<'s>),
+    Composes(Composes<'s>),
+    /// Name of a keyframes definition: `@keyframes name { ... }`
+    Keyframes(&'s str),
+    /// Identifier inside an `animation` or `animation-name` declaration that
+    /// might reference a keyframes definition.
+    AnimationName(&'s str),
+    /// Name (without the leading `--`) of a custom property declaration: `--name: value;`
+    CustomProperty(&'s str),
+    /// Name (without the leading `--`) of a custom property used in `var(--name)`
+    VarReference(&'s str),
+    /// Span that must be removed from the output, such as the `:local(` and `)` around
+    /// a local selector or the `:global {` and `}` around a global block.
+    Remove(&'s str),
+    Export(Export<'s>),
 }
 
-pub fn parse_css(input: &str) -> Result<Vec<CssFragment<'_>>, ParseError<&str, ContextError>> {
-    style_rule_block_contents.parse(input)
+/// Parses a css module, `default_scope` determines whether class names outside of
+/// `:global` and `:local` are scoped or not.
+pub fn parse_css<'s>(
+    input: &'s str,
+    default_scope: Scope,
+) -> Result<Vec<CssFragment<'s>>, ParseError<&'s str, ContextError>> {
+    (|i: &mut &'s str| style_rule_block_contents(i, default_scope)).parse(input)
+}
+
+/// Finds the class names of a selector, such as the inner span of a `:global(...)`.
+pub fn selector_classes(mut input: &str) -> Vec<&str> {
+    repeat(
+        0..,
+        alt((
+            class.map(Some),
+            string.map(|_| None),
+            block_comment.map(|_| None),
+            any.map(|_| None),
+        )),
+    )
+    .fold(Vec::new, |mut acc, class| {
+        acc.extend(class);
+        acc
+    })
+    .parse_next(&mut input)
+    .unwrap_or_default()
+}
+
+/// Matches a closing token, reporting it as expected when it's missing.
+fn closing<'s>(token: &'static str) -> impl ModalParser<&'s str, &'s str, ContextError> {
+    token.context(StrContext::Expected(StrContextValue::StringLiteral(token)))
 }
 
 pub fn take_repeat<'s, O>(
@@ -53,7 +138,10 @@ fn line_comment<'s>(input: &mut &'s str) -> ModalResult<&'s str> {
 }
 
 fn block_comment<'s>(input: &mut &'s str) -> ModalResult<&'s str> {
-    ("/*", cut_err(terminated(take_until(0.., "*/"), "*/")))
+    (
+        "/*",
+        cut_err(terminated(take_until(0.., "*/"), closing("*/"))),
+    )
         .take()
         .parse_next(input)
 }
@@ -62,7 +150,7 @@ fn block_comment<'s>(input: &mut &'s str) -> ModalResult<&'s str> {
 fn sass_interpolation<'s>(input: &mut &'s str) -> ModalResult<&'s str> {
     (
         "#{",
-        cut_err(terminated(take_till(1.., ('{', '}', '\n')), '}')),
+        cut_err(terminated(take_till(1.., ('{', '}', '\n')), closing("}"))),
     )
         .take()
         .parse_next(input)
@@ -86,7 +174,7 @@ fn global<'s>(input: &mut &'s str) -> ModalResult<Global<'s>> {
         ":global(",
         cut_err(terminated(
             stuff_till(0.., (')', '(', '{')), // inner
-            ')',
+            closing(")"),
         )),
     )
     .with_taken() // outer
@@ -94,18 +182,57 @@ fn global<'s>(input: &mut &'s str) -> ModalResult<Global<'s>> {
     Ok(Global { inner, outer })
 }
 
+/// ```text
+/// :local(.foo .bar)
+/// ```
+/// The wrapper is reported as `Remove` fragments and the classes inside are local.
+fn local<'s>(input: &mut &'s str) -> ModalResult<Vec<CssFragment<'s>>> {
+    let (open, mut classes, close) = (
+        ":local(",
+        cut_err(
+            repeat(
+                0..,
+                alt((
+                    class.map(Some),
+                    stuff_till(1.., ('.', ')', '(', '{')).map(|_| None),
+                )),
+            )
+            .fold(Vec::new, |mut acc, item| {
+                acc.extend(item.map(CssFragment::Class));
+                acc
+            }),
+        ),
+        cut_err(closing(")")),
+    )
+        .parse_next(input)?;
+
+    classes.insert(0, CssFragment::Remove(open));
+    classes.push(CssFragment::Remove(close));
+    Ok(classes)
+}
+
+/// Bare `:global` or `:local` inside a selector, switches the scope for the rest of it.
+fn scope_switch<'s>(input: &mut &'s str) -> ModalResult<(Scope, &'s str)> {
+    terminated(
+        alt((":global".value(Scope::Global), ":local".value(Scope::Local))),
+        not(one_of(('_', '-', '(', AsChar::is_alphanum))),
+    )
+    .with_taken()
+    .parse_next(input)
+}
+
 fn string_dq<'s>(input: &mut &'s str) -> ModalResult<&'s str> {
     let str_char = alt((none_of(['"']).void(), "\\\"".void()));
     let str_chars = take_repeat(0.., str_char);
 
-    preceded('"', cut_err(terminated(str_chars, '"'))).parse_next(input)
+    preceded('"', cut_err(terminated(str_chars, closing("\"")))).parse_next(input)
 }
 
 fn string_sq<'s>(input: &mut &'s str) -> ModalResult<&'s str> {
     let str_char = alt((none_of(['\'']).void(), "\\'".void()));
     let str_chars = take_repeat(0.., str_char);
 
-    preceded('\'', cut_err(terminated(str_chars, '\''))).parse_next(input)
+    preceded('\'', cut_err(terminated(str_chars, closing("'")))).parse_next(input)
 }
 
 fn string<'s>(input: &mut &'s str) -> ModalResult<&'s str> {
@@ -132,14 +259,83 @@ pub fn stuff_till<'s>(
     )
 }
 
-fn selector<'s>(input: &mut &'s str) -> ModalResult<Vec<CssFragment<'s>>> {
+enum SelectorItem<'s> {
+    Class(&'s str),
+    Fragments(Vec<CssFragment<'s>>),
+    Switch(Scope, &'s str),
+    Comma,
+    Open,
+    Close,
+    Other,
+}
+
+/// Parses a selector starting in `scope`, returns the fragments found and the
+/// scope that is active at the end of the selector (which applies to nested rules).
+fn selector<'s>(input: &mut &'s str, scope: Scope) -> ModalResult<(Vec<CssFragment<'s>>, Scope)> {
     repeat(
         1..,
         alt((
-            class.map(|c| Some(CssFragment::Class(c))),
-            global.map(|g| Some(CssFragment::Global(g))),
-            ':'.map(|_| None),
-            stuff_till(1.., ('.', ';', '{', '}', ':')).map(|_| None),
+            class.map(SelectorItem::Class),
+            global.map(|g| SelectorItem::Fragments(vec![CssFragment::Global(g)])),
+            local.map(SelectorItem::Fragments),
+            scope_switch.map(|(s, span)| SelectorItem::Switch(s, span)),
+            ':'.map(|_| SelectorItem::Other),
+            ','.map(|_| SelectorItem::Comma),
+            '('.map(|_| SelectorItem::Open),
+            ')'.map(|_| SelectorItem::Close),
+            stuff_till(1.., ('.', ';', '{', '}', ':', ',', '(', ')')).map(|_| SelectorItem::Other),
+        )),
+    )
+    .fold(
+        || (Vec::new(), scope, 0usize),
+        |(mut acc, mut current, mut depth), item| {
+            match item {
+                SelectorItem::Class(c) => acc.push(match current {
+                    Scope::Local => CssFragment::Class(c),
+                    Scope::Global => CssFragment::GlobalClass(c),
+                }),
+                SelectorItem::Fragments(mut f) => acc.append(&mut f),
+                SelectorItem::Switch(s, span) => {
+                    current = s;
+                    acc.push(CssFragment::Remove(span));
+                }
+                // Each selector in a list starts with the scope of the rule, the commas
+                // inside of `:is(...)` and similar arguments don't start a new selector.
+                SelectorItem::Comma if depth == 0 => current = scope,
+                SelectorItem::Open => depth += 1,
+                SelectorItem::Close => depth = depth.saturating_sub(1),
+                SelectorItem::Comma | SelectorItem::Other => {}
+            }
+            (acc, current, depth)
+        },
+    )
+    .map(|(acc, current, _)| (acc, current))
+    .parse_next(input)
+}
+
+/// Matches a custom property name `--name` and returns the name without the dashes.
+fn custom_property_name<'s>(input: &mut &'s str) -> ModalResult<&'s str> {
+    preceded("--", take_while(1.., ('_', '-', AsChar::is_alphanum))).parse_next(input)
+}
+
+/// Matches the start of a `var(--name` function returning the custom property name.
+fn var_reference<'s>(input: &mut &'s str) -> ModalResult<&'s str> {
+    preceded(("var(", ws), custom_property_name).parse_next(input)
+}
+
+/// Behaves like `stuff_till(1.., (';', '{', '}'))` but reports the custom properties
+/// referenced with `var()`.
+fn declaration_value<'s>(input: &mut &'s str) -> ModalResult<Vec<CssFragment<'s>>> {
+    repeat(
+        1..,
+        alt((
+            var_reference.map(|v| Some(CssFragment::VarReference(v))),
+            string.map(|_| None),
+            block_comment.map(|_| None),
+            line_comment.map(|_| None),
+            sass_interpolation.map(|_| None),
+            take_till(1.., ('\'', '"', '/', '#', 'v', ';', '{', '}')).map(|_| None),
+            one_of(('/', '#', 'v')).map(|_| None),
         )),
     )
     .fold(Vec::new, |mut acc, item| {
@@ -151,31 +347,103 @@ fn selector<'s>(input: &mut &'s str) -> ModalResult<Vec<CssFragment<'s>>> {
     .parse_next(input)
 }
 
-fn declaration<'s>(input: &mut &'s str) -> ModalResult<&'s str> {
-    (
-        (opt('$'), identifier),
+fn declaration<'s>(input: &mut &'s str, scope: Scope) -> ModalResult<Vec<CssFragment<'s>>> {
+    let (name, _, _, mut value) = (
+        (opt('$'), identifier).take(),
         ws,
         ':',
         terminated(
-            stuff_till(1.., (';', '{', '}')),
+            declaration_value,
             alt((';', peek('}'))), // semicolon is optional if it's the last element in a rule block
         ),
     )
-        .take()
-        .parse_next(input)
+        .parse_next(input)?;
+
+    let mut fragments = Vec::new();
+    if let Some(custom_property) = name.strip_prefix("--").filter(|_| scope == Scope::Local) {
+        fragments.push(CssFragment::CustomProperty(custom_property));
+    }
+    fragments.append(&mut value);
+
+    Ok(fragments)
+}
+
+fn composes<'s>(input: &mut &'s str) -> ModalResult<Composes<'s>> {
+    let composes_name = identifier.verify(|s: &str| s != "from");
+
+    let ((names, from), span) = preceded(
+        ("composes", ws, ':', ws),
+        cut_err(terminated(
+            (
+                repeat(1.., terminated(composes_name, ws)),
+                opt(preceded(
+                    ("from", ws),
+                    alt((
+                        string.map(ComposesFrom::File),
+                        "global".value(ComposesFrom::Global),
+                    )),
+                ))
+                .map(|from| from.unwrap_or(ComposesFrom::Local)),
+            ),
+            (ws, alt((';', peek('}')))),
+        )),
+    )
+    .with_taken()
+    .parse_next(input)?;
+
+    Ok(Composes {
+        owners: None,
+        names,
+        from,
+        span,
+    })
 }
 
-fn style_rule_block_statement<'s>(input: &mut &'s str) -> ModalResult<Vec<CssFragment<'s>>> {
+fn export_block<'s>(input: &mut &'s str) -> ModalResult<Export<'s>> {
+    let entry = delimited(
+        ws,
+        (
+            identifier,
+            ws,
+            ':',
+            ws,
+            stuff_till(1.., (';', '}')).map(str::trim_end),
+        ),
+        (ws, opt(';'), ws),
+    )
+    .map(|(name, _, _, _, value)| (name, value));
+
+    let (values, span) = preceded(
+        (":export", ws, '{'),
+        cut_err(terminated(repeat(0.., entry), (ws, closing("}")))),
+    )
+    .with_taken()
+    .parse_next(input)?;
+
+    Ok(Export { values, span })
+}
+
+fn style_rule_block_statement<'s>(
+    input: &mut &'s str,
+    scope: Scope,
+) -> ModalResult<Vec<CssFragment<'s>>> {
     let content = alt((
-        declaration.map(|_| Vec::new()), //
-        at_rule,
-        style_rule,
+        composes.map(|c| vec![CssFragment::Composes(c)]),
+        |i: &mut &'s str| animation_declaration(i, scope),
+        |i: &mut &'s str| declaration(i, scope),
+        |i: &mut &'s str| at_rule(i, scope),
+        export_block.map(|e| vec![CssFragment::Export(e)]),
+        scope_block,
+        |i: &mut &'s str| style_rule(i, scope),
     ));
     delimited(ws, content, ws).parse_next(input)
 }
 
-fn style_rule_block_contents<'s>(input: &mut &'s str) -> ModalResult<Vec<CssFragment<'s>>> {
-    repeat(0.., style_rule_block_statement)
+fn style_rule_block_contents<'s>(
+    input: &mut &'s str,
+    scope: Scope,
+) -> ModalResult<Vec<CssFragment<'s>>> {
+    repeat(0.., |i: &mut &'s str| style_rule_block_statement(i, scope))
         .fold(Vec::new, |mut acc, mut item| {
             acc.append(&mut item);
             acc
@@ -183,49 +451,189 @@ fn style_rule_block_contents<'s>(input: &mut &'s str) -> ModalResult<Vec<CssFrag
         .parse_next(input)
 }
 
-fn style_rule_block<'s>(input: &mut &'s str) -> ModalResult<Vec<CssFragment<'s>>> {
+fn style_rule_block<'s>(input: &mut &'s str, scope: Scope) -> ModalResult<Vec<CssFragment<'s>>> {
     preceded(
         '{',
-        cut_err(terminated(style_rule_block_contents, (ws, '}'))),
+        cut_err(terminated(
+            |i: &mut &'s str| style_rule_block_contents(i, scope),
+            (ws, closing("}")),
+        )),
     )
     .parse_next(input)
 }
 
-fn style_rule<'s>(input: &mut &'s str) -> ModalResult<Vec<CssFragment<'s>>> {
-    let (mut classes, mut nested_classes) = (selector, style_rule_block).parse_next(input)?;
+/// Block form of `:global` and `:local`, the wrapper is removed and the
+/// contents are parsed with the corresponding scope, whatever the scope of
+/// the enclosing rule is.
+///
+/// ```text
+/// :global {
+///     .foo { color: red; }
+/// }
+/// ```
+fn scope_block<'s>(input: &mut &'s str) -> ModalResult<Vec<CssFragment<'s>>> {
+    let ((scope, _), open) = (scope_switch, ws, '{')
+        .map(|(switch, _, _)| switch)
+        .with_taken()
+        .parse_next(input)?;
+
+    let (mut contents, close) = cut_err((
+        |i: &mut &'s str| style_rule_block_contents(i, scope),
+        (ws, closing("}")).take(),
+    ))
+    .parse_next(input)?;
+
+    contents.insert(0, CssFragment::Remove(open));
+    contents.push(CssFragment::Remove(close));
+    Ok(contents)
+}
+
+fn style_rule<'s>(input: &mut &'s str, scope: Scope) -> ModalResult<Vec<CssFragment<'s>>> {
+    let (mut classes, nested_scope) = selector(input, scope)?;
+    let mut nested_classes = style_rule_block(input, nested_scope)?;
+
+    // Composes declarations apply to the classes of the rule they are declared in.
+    let owners = classes
+        .iter()
+        .filter_map(|f| match f {
+            CssFragment::Class(c) => Some(*c),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    for fragment in nested_classes.iter_mut() {
+        if let CssFragment::Composes(c) = fragment {
+            c.owners.get_or_insert_with(|| owners.clone());
+        }
+    }
+
     classes.append(&mut nested_classes);
     Ok(classes)
 }
 
-fn at_rule<'s>(input: &mut &'s str) -> ModalResult<Vec<CssFragment<'s>>> {
-    let (identifier, char) = preceded(
-        '@',
-        cut_err((
-            terminated(identifier, stuff_till(0.., ('{', '}', ';'))),
-            alt(('{', ';', peek('}'))),
+fn vendor_prefix<'s>(input: &mut &'s str) -> ModalResult<&'s str> {
+    alt(("-webkit-", "-moz-", "-o-", "-ms-")).parse_next(input)
+}
+
+/// Parses the values of an `animation` or `animation-name` declaration, the identifiers
+/// found are reported as possible references to keyframes. In global scope the names are
+/// left as they are.
+fn animation_declaration<'s>(
+    input: &mut &'s str,
+    scope: Scope,
+) -> ModalResult<Vec<CssFragment<'s>>> {
+    let word = take_while(1.., ('_', '-', '.', '%', AsChar::is_alphanum));
+
+    let value = repeat(
+        0..,
+        alt((
+            global.map(|g| Some(CssFragment::Global(g))),
+            ('$', identifier).map(|_| None),
+            sass_interpolation.map(|_| None),
+            string.map(|_| None),
+            block_comment.map(|_| None),
+            line_comment.map(|_| None),
+            var_reference.map(|v| Some(CssFragment::VarReference(v))),
+            word.map(|w: &str| {
+                (scope == Scope::Local && identifier.parse(w).is_ok())
+                    .then_some(CssFragment::AnimationName(w))
+            }),
+            none_of([';', '{', '}']).map(|_| None),
         )),
     )
+    .fold(Vec::new, |mut acc, item| {
+        if let Some(item) = item {
+            acc.push(item);
+        }
+        acc
+    });
+
+    preceded(
+        (
+            opt(vendor_prefix),
+            alt(("animation-name", "animation")),
+            ws,
+            ':',
+        ),
+        terminated(value, alt((';', peek('}')))),
+    )
+    .parse_next(input)
+}
+
+/// Name of a keyframes at-rule, either a plain identifier or wrapped with `:global()`
+/// or `:local()`.
+fn keyframes_name<'s>(input: &mut &'s str, scope: Scope) -> ModalResult<Vec<CssFragment<'s>>> {
+    let local_name =
+        ((":local(", ws).take(), identifier, (ws, ')').take()).map(|(open, name, close)| {
+            vec![
+                CssFragment::Remove(open),
+                CssFragment::Keyframes(name),
+                CssFragment::Remove(close),
+            ]
+        });
+
+    alt((
+        global.map(|g| vec![CssFragment::Global(g)]),
+        local_name,
+        terminated(
+            identifier,
+            peek(one_of((AsChar::is_space, '\n', '\r', '{'))),
+        )
+        .map(|name| match scope {
+            Scope::Local => vec![CssFragment::Keyframes(name)],
+            Scope::Global => vec![],
+        }),
+    ))
+    .parse_next(input)
+}
+
+fn at_rule<'s>(input: &mut &'s str, scope: Scope) -> ModalResult<Vec<CssFragment<'s>>> {
+    let identifier = preceded('@', cut_err(identifier)).parse_next(input)?;
+
+    let mut fragments = Vec::new();
+
+    if matches!(
+        identifier,
+        "keyframes" | "-webkit-keyframes" | "-moz-keyframes" | "-o-keyframes"
+    ) {
+        fragments.extend(
+            opt(preceded(ws, |i: &mut &'s str| keyframes_name(i, scope)))
+                .parse_next(input)?
+                .unwrap_or_default(),
+        );
+    } else if identifier == "property" && scope == Scope::Local {
+        fragments.extend(
+            opt(preceded(ws, custom_property_name))
+                .parse_next(input)?
+                .map(CssFragment::CustomProperty),
+        );
+    }
+
+    let char = cut_err(preceded(
+        stuff_till(0.., ('{', '}', ';')),
+        alt(('{', ';', peek('}'))),
+    ))
     .parse_next(input)?;
 
     if char != '{' {
-        return Ok(vec![]);
+        return Ok(fragments);
     }
 
     match identifier {
         "media" | "layer" | "container" | "include" => {
-            cut_err(terminated(style_rule_block_contents, '}')).parse_next(input)
+            fragments.append(
+                &mut cut_err(terminated(
+                    |i: &mut &'s str| style_rule_block_contents(i, scope),
+                    closing("}"),
+                ))
+                .parse_next(input)?,
+            );
         }
         _ => {
-            cut_err(terminated(unknown_block_contents, '}')).parse_next(input)?;
-            Ok(vec![])
+            cut_err(terminated(unknown_block_contents, closing("}"))).parse_next(input)?;
         }
     }
-    // if identifier == "media" {
-    //     cut_err(terminated(style_rule_block_contents, '}')).parse_next(input)
-    // } else {
-    //     cut_err(terminated(unknown_block_contents, '}')).parse_next(input)?;
-    //     Ok(vec![])
-    // }
+
+    Ok(fragments)
 }
 
 fn unknown_block_contents<'s>(input: &mut &'s str) -> ModalResult<&'s str> {
@@ -233,7 +641,7 @@ fn unknown_block_contents<'s>(input: &mut &'s str) -> ModalResult<&'s str> {
         0..,
         alt((
             stuff_till(1.., ('{', '}')).void(),
-            ('{', cut_err((unknown_block_contents, '}'))).void(),
+            ('{', cut_err((unknown_block_contents, closing("}")))).void(),
         )),
     )
     .parse_next(input)
@@ -251,11 +659,17 @@ mod tests {
         assert_eq!(r, Ok("_x1a2b"));
     }
 
+    #[test]
+    fn test_selector_classes() {
+        let r = selector_classes(".foo.bar > div[value=\"fa.sdasd\"] /* .banana */ :hover .cry");
+        assert_eq!(r, vec!["foo", "bar", "cry"]);
+    }
+
     #[test]
     fn test_selector() {
         let mut input = ".foo.bar [value=\"fa.sdasd\"] /* .banana */ // .apple \n \t .cry {";
 
-        let r = selector.parse_next(&mut input);
+        let r = selector(&mut input, Scope::Local).map(|(f, _)| f);
         assert_eq!(
             r,
             Ok(vec![
@@ -267,7 +681,9 @@ mod tests {
 
         let mut input = "{";
 
-        let r = selector.take().parse_next(&mut input);
+        let r = (|i: &mut &'static str| selector(i, Scope::Local))
+            .take()
+            .parse_next(&mut input);
         assert!(r.is_err());
     }
 
@@ -275,13 +691,315 @@ mod tests {
     fn test_declaration() {
         let mut input = "background-color \t : red;";
 
-        let r = declaration.parse_next(&mut input);
+        let r = (|i: &mut &'static str| declaration(i, Scope::Local))
+            .take()
+            .parse_next(&mut input);
         assert_eq!(r, Ok("background-color \t : red;"));
 
-        let r = declaration.parse_next(&mut input);
+        let r = declaration(&mut input, Scope::Local);
         assert!(r.is_err());
     }
 
+    #[test]
+    fn test_custom_properties() {
+        let mut input = "--main-color: var(--base, var( --fallback)) \"var(--in-string)\";";
+        let r = declaration(&mut input, Scope::Local);
+        assert_eq!(
+            r,
+            Ok(vec![
+                CssFragment::CustomProperty("main-color"),
+                CssFragment::VarReference("base"),
+                CssFragment::VarReference("fallback"),
+            ])
+        );
+        assert!(input.is_empty());
+
+        let mut input = "animation: var(--anim) spin;";
+        let r = animation_declaration(&mut input, Scope::Local);
+        assert_eq!(
+            r,
+            Ok(vec![
+                CssFragment::VarReference("anim"),
+                CssFragment::AnimationName("spin"),
+            ])
+        );
+
+        let mut input = "@property --angle { syntax: '<angle>'; inherits: false; }";
+        let r = at_rule(&mut input, Scope::Local);
+        assert_eq!(r, Ok(vec![CssFragment::CustomProperty("angle")]));
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn test_composes() {
+        let mut input = "composes: foo bar;END";
+        let r = composes.parse_next(&mut input);
+        assert_eq!(
+            r,
+            Ok(Composes {
+                owners: None,
+                names: vec!["foo", "bar"],
+                from: ComposesFrom::Local,
+                span: "composes: foo bar;",
+            })
+        );
+        assert_eq!(input, "END");
+
+        let mut input = "composes : foo from global }";
+        let r = composes.parse_next(&mut input);
+        assert_eq!(
+            r,
+            Ok(Composes {
+                owners: None,
+                names: vec!["foo"],
+                from: ComposesFrom::Global,
+                span: "composes : foo from global ",
+            })
+        );
+        assert_eq!(input, "}");
+
+        let mut input = "composes: foo from \"./other.module.css\";";
+        let r = composes.parse_next(&mut input);
+        assert_eq!(
+            r,
+            Ok(Composes {
+                owners: None,
+                names: vec!["foo"],
+                from: ComposesFrom::File("./other.module.css"),
+                span: "composes: foo from \"./other.module.css\";",
+            })
+        );
+
+        let mut input = "composes: from \"./other.module.css\";";
+        assert!(composes.parse_next(&mut input).is_err());
+    }
+
+    #[test]
+    fn test_style_rule_composes() {
+        let mut input = ".foo {
+            color: red;
+            composes: bar;
+            .baz {
+                composes: qux from global;
+            }
+        }";
+
+        let r = style_rule(&mut input, Scope::Local);
+        assert_eq!(
+            r,
+            Ok(vec![
+                CssFragment::Class("foo"),
+                CssFragment::Composes(Composes {
+                    owners: Some(vec!["foo"]),
+                    names: vec!["bar"],
+                    from: ComposesFrom::Local,
+                    span: "composes: bar;",
+                }),
+                CssFragment::Class("baz"),
+                CssFragment::Composes(Composes {
+                    owners: Some(vec!["baz"]),
+                    names: vec!["qux"],
+                    from: ComposesFrom::Global,
+                    span: "composes: qux from global;",
+                }),
+            ])
+        );
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn test_at_rule_keyframes() {
+        let mut input = "@keyframes fade-in {
+            from { opacity: 0; }
+            to { opacity: 1; }
+        }";
+        let r = at_rule(&mut input, Scope::Local);
+        assert_eq!(r, Ok(vec![CssFragment::Keyframes("fade-in")]));
+        assert!(input.is_empty());
+
+        let mut input = "@-webkit-keyframes :global(fade-in) { from { opacity: 0; } }";
+        let r = at_rule(&mut input, Scope::Local);
+        assert_eq!(
+            r,
+            Ok(vec![CssFragment::Global(Global {
+                inner: "fade-in",
+                outer: ":global(fade-in)"
+            })])
+        );
+        assert!(input.is_empty());
+
+        let mut input = "@keyframes #{$name} { from { opacity: 0; } }";
+        let r = at_rule(&mut input, Scope::Local);
+        assert_eq!(r, Ok(vec![]));
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn test_animation_declaration() {
+        let mut input = "animation: 1.5s ease-in $delay spin, -0.5s :global(fade) \"str\";";
+        let r = animation_declaration(&mut input, Scope::Local);
+        assert_eq!(
+            r,
+            Ok(vec![
+                CssFragment::AnimationName("ease-in"),
+                CssFragment::AnimationName("spin"),
+                CssFragment::Global(Global {
+                    inner: "fade",
+                    outer: ":global(fade)"
+                }),
+            ])
+        );
+        assert!(input.is_empty());
+
+        let mut input = "-webkit-animation-name : spin }";
+        let r = animation_declaration(&mut input, Scope::Local);
+        assert_eq!(r, Ok(vec![CssFragment::AnimationName("spin")]));
+        assert_eq!(input, "}");
+
+        let mut input = "animation: spin 1s;";
+        let r = animation_declaration(&mut input, Scope::Global);
+        assert_eq!(r, Ok(vec![]));
+
+        let mut input = "animation-duration: 1s;";
+        assert!(animation_declaration(&mut input, Scope::Local).is_err());
+    }
+
+    #[test]
+    fn test_selector_scope_switch() {
+        let mut input = ".a :global .b :local .c, .d :local(.e) :local-link {";
+        let r = selector(&mut input, Scope::Local);
+        assert_eq!(
+            r,
+            Ok((
+                vec![
+                    CssFragment::Class("a"),
+                    CssFragment::Remove(":global"),
+                    CssFragment::GlobalClass("b"),
+                    CssFragment::Remove(":local"),
+                    CssFragment::Class("c"),
+                    CssFragment::Class("d"),
+                    CssFragment::Remove(":local("),
+                    CssFragment::Class("e"),
+                    CssFragment::Remove(")"),
+                ],
+                Scope::Local
+            ))
+        );
+
+        let mut input = ":global :is(.a, .b) .c, .d {";
+        let r = selector(&mut input, Scope::Local);
+        assert_eq!(
+            r,
+            Ok((
+                vec![
+                    CssFragment::Remove(":global"),
+                    CssFragment::GlobalClass("a"),
+                    CssFragment::GlobalClass("b"),
+                    CssFragment::GlobalClass("c"),
+                    CssFragment::Class("d"),
+                ],
+                Scope::Local
+            ))
+        );
+
+        let mut input = ".a, :local .b :global .c {";
+        let r = selector(&mut input, Scope::Global);
+        assert_eq!(
+            r,
+            Ok((
+                vec![
+                    CssFragment::GlobalClass("a"),
+                    CssFragment::Remove(":local"),
+                    CssFragment::Class("b"),
+                    CssFragment::Remove(":global"),
+                    CssFragment::GlobalClass("c"),
+                ],
+                Scope::Global
+            ))
+        );
+    }
+
+    #[test]
+    fn test_scope_blocks() {
+        let mut input = ":global {
+            .a { color: red; }
+            .b :local .c { color: red; }
+            :local {
+                .d { color: red; }
+                @keyframes spin { to { opacity: 0; } }
+            }
+            @keyframes fade { to { opacity: 0; } }
+        }
+        .e :global { .f { color: red; } }
+        :global(.g) { .h { color: red; } }";
+
+        let r = style_rule_block_contents(&mut input, Scope::Local);
+        assert_eq!(
+            r,
+            Ok(vec![
+                CssFragment::Remove(":global {"),
+                CssFragment::GlobalClass("a"),
+                CssFragment::GlobalClass("b"),
+                CssFragment::Remove(":local"),
+                CssFragment::Class("c"),
+                CssFragment::Remove(":local {"),
+                CssFragment::Class("d"),
+                CssFragment::Keyframes("spin"),
+                CssFragment::Remove("}"),
+                CssFragment::Remove("}"),
+                CssFragment::Class("e"),
+                CssFragment::Remove(":global"),
+                CssFragment::GlobalClass("f"),
+                CssFragment::Global(Global {
+                    inner: ".g",
+                    outer: ":global(.g)"
+                }),
+                CssFragment::Class("h"),
+            ])
+        );
+        assert!(input.is_empty());
+
+        let mut input = "@keyframes :local(spin) { to { opacity: 0; } }";
+        let r = at_rule(&mut input, Scope::Global);
+        assert_eq!(
+            r,
+            Ok(vec![
+                CssFragment::Remove(":local("),
+                CssFragment::Keyframes("spin"),
+                CssFragment::Remove(")"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_export_block() {
+        let mut input = ":export {
+            breakpoint: 768px;
+            primary-color : #f00 ; // comment
+            font: \"Helvetica; Arial\", sans-serif
+        }END";
+        let r = export_block.parse_next(&mut input);
+        assert_eq!(
+            r.map(|e| e.values),
+            Ok(vec![
+                ("breakpoint", "768px"),
+                ("primary-color", "#f00"),
+                ("font", "\"Helvetica; Arial\", sans-serif"),
+            ])
+        );
+        assert_eq!(input, "END");
+
+        let mut input = ":export { }";
+        let r = export_block.parse_next(&mut input);
+        assert_eq!(
+            r,
+            Ok(Export {
+                values: vec![],
+                span: ":export { }"
+            })
+        );
+    }
+
     #[test]
     fn test_style_rule() {
         let mut input = ".foo.bar {
@@ -303,7 +1021,7 @@ mod tests {
         }
     }END";
 
-        let r = style_rule.parse_next(&mut input);
+        let r = style_rule(&mut input, Scope::Local);
         assert_eq!(
             r,
             Ok(vec![
@@ -322,7 +1040,7 @@ mod tests {
     fn test_at_rule_simple() {
         let mut input = "@simple-rule blah \"asd;asd\" blah;";
 
-        let r = at_rule.parse_next(&mut input);
+        let r = at_rule(&mut input, Scope::Local);
         assert_eq!(r, Ok(vec![]));
 
         assert!(input.is_empty());
@@ -346,7 +1064,7 @@ mod tests {
         }
     }";
 
-        let r = at_rule.parse_next(&mut input);
+        let r = at_rule(&mut input, Scope::Local);
         assert_eq!(r, Ok(vec![]));
 
         assert!(input.is_empty());
@@ -368,7 +1086,7 @@ mod tests {
         }
     }";
 
-        let r = at_rule.parse_next(&mut input);
+        let r = at_rule(&mut input, Scope::Local);
         assert_eq!(
             r,
             Ok(vec![
@@ -397,7 +1115,7 @@ mod tests {
         }
     }";
 
-        let r = at_rule.parse_next(&mut input);
+        let r = at_rule(&mut input, Scope::Local);
         assert_eq!(
             r,
             Ok(vec![
@@ -462,7 +1180,7 @@ mod tests {
         @debug 1+2 * 3==1+(2 * 3); // true
         ";
 
-        let r = style_rule_block_contents.parse_next(&mut input);
+        let r = style_rule_block_contents(&mut input, Scope::Local);
         assert_eq!(
             r,
             Ok(vec![
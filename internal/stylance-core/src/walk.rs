@@ -0,0 +1,220 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use ignore::{gitignore::Gitignore, Match, WalkBuilder};
+
+use crate::{path_utils, Config};
+
+/// Finds the css module files inside of the folders of a crate, sorted by path.
+///
+/// Files ignored by `.gitignore` or `.ignore` files and cargo target directories are skipped.
+pub fn find_css_modules(config: &Config) -> Vec<PathBuf> {
+    let mut css_files = config
+        .folders
+        .iter()
+        .flat_map(|folder| walk_files(folder))
+        .filter(|path| config.is_css_module(path))
+        .collect::<Vec<_>>();
+
+    css_files.sort();
+    css_files
+}
+
+/// Walks the files inside of `folder` with the same rules as [`find_css_modules`].
+pub fn walk_files(folder: &Path) -> impl Iterator<Item = PathBuf> {
+    WalkBuilder::new(folder)
+        .standard_filters(false)
+        .git_ignore(true)
+        .ignore(true)
+        .parents(true)
+        .filter_entry(|entry| {
+            !(entry.file_type().is_some_and(|t| t.is_dir()) && is_target_dir(entry.path()))
+        })
+        .build()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+        .map(|entry| entry.into_path())
+}
+
+/// Whether cargo uses a directory as its target directory.
+///
+/// Cargo marks its target directories with a `CACHEDIR.TAG` file, the `target` folder
+/// next to a `Cargo.toml` is also skipped in case it wasn't created by a recent cargo.
+fn is_target_dir(path: &Path) -> bool {
+    path.join("CACHEDIR.TAG").is_file()
+        || (path.file_name().is_some_and(|name| name == "target")
+            && path.with_file_name("Cargo.toml").is_file())
+}
+
+/// Filters the files reported by a file watcher with the rules of [`find_css_modules`].
+///
+/// The ignore files of each folder are read the first time a path inside of it is checked,
+/// a matcher is meant to be created for each config the watcher runs with.
+#[derive(Default)]
+pub struct IgnoreMatcher {
+    dirs: HashMap<PathBuf, DirRules>,
+}
+
+struct DirRules {
+    is_target_dir: bool,
+    is_git_root: bool,
+    ignore: Option<Gitignore>,
+    gitignore: Option<Gitignore>,
+}
+
+impl DirRules {
+    fn load(dir: &Path) -> Self {
+        let ignore_file = |name| {
+            let path = dir.join(name);
+            path.is_file().then(|| Gitignore::new(path).0)
+        };
+
+        Self {
+            is_target_dir: is_target_dir(dir),
+            is_git_root: dir.join(".git").exists(),
+            ignore: ignore_file(".ignore"),
+            gitignore: ignore_file(".gitignore"),
+        }
+    }
+}
+
+fn matched(ignore_file: Option<&Gitignore>, path: &Path) -> Option<bool> {
+    match ignore_file?.matched_path_or_any_parents(path, false) {
+        Match::Ignore(_) => Some(true),
+        Match::Whitelist(_) => Some(false),
+        Match::None => None,
+    }
+}
+
+impl IgnoreMatcher {
+    /// Whether a file is inside of a cargo target directory or is ignored by a `.gitignore`
+    /// or `.ignore` file.
+    ///
+    /// Like in discovery, the `.ignore` files of every parent folder take precedence over the
+    /// `.gitignore` files, which only apply inside of a git repository up to its root.
+    pub fn is_ignored(&mut self, path: &Path) -> bool {
+        let Ok(path) = path_utils::normalize(path) else {
+            return false;
+        };
+
+        // `Some(true)` when the path is ignored, `Some(false)` when it's whitelisted.
+        let mut ignore_match = None;
+        let mut gitignore_match = None;
+        let mut saw_git_root = false;
+
+        for dir in path.ancestors().skip(1) {
+            let rules = self
+                .dirs
+                .entry(dir.to_owned())
+                .or_insert_with(|| DirRules::load(dir));
+
+            if !saw_git_root && rules.is_target_dir {
+                return true;
+            }
+
+            if ignore_match.is_none() {
+                ignore_match = matched(rules.ignore.as_ref(), &path);
+            }
+            if gitignore_match.is_none() && !saw_git_root {
+                gitignore_match = matched(rules.gitignore.as_ref(), &path);
+            }
+
+            saw_git_root |= rules.is_git_root;
+        }
+
+        // Like git, `.gitignore` files only apply inside of a repository.
+        ignore_match
+            .or(gitignore_match.filter(|_| saw_git_root))
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::PartialConfig;
+
+    #[test]
+    fn test_ignored_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for path in [
+            "src/a.module.css",
+            "src/old.module.css",
+            "src/generated/b.module.css",
+            "node_modules/pkg/c.module.css",
+            "target/debug/d.module.css",
+            "other-target/e.module.css",
+        ] {
+            fs::create_dir_all(root.join(path).parent().unwrap()).unwrap();
+            fs::write(root.join(path), ".a { color: red; }").unwrap();
+        }
+        fs::create_dir(root.join(".git")).unwrap();
+        fs::write(root.join("Cargo.toml"), "").unwrap();
+        fs::write(root.join("other-target/CACHEDIR.TAG"), "").unwrap();
+        fs::write(root.join(".gitignore"), "/node_modules\ngenerated/\n").unwrap();
+        fs::write(root.join("src/.ignore"), "old.module.css\n").unwrap();
+
+        let config = Config::from_partials(
+            root.to_path_buf(),
+            PartialConfig {
+                folders: Some(vec![PathBuf::from(".")]),
+                ..Default::default()
+            },
+            None,
+        )
+        .unwrap();
+
+        let found = find_css_modules(&config)
+            .iter()
+            .map(|p| p.strip_prefix(root.join(".")).unwrap().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(found, [PathBuf::from("src/a.module.css")]);
+
+        let mut matcher = IgnoreMatcher::default();
+        assert!(!matcher.is_ignored(&root.join("src/a.module.css")));
+        assert!(matcher.is_ignored(&root.join("src/old.module.css")));
+        assert!(matcher.is_ignored(&root.join("src/generated/b.module.css")));
+        assert!(matcher.is_ignored(&root.join("node_modules/pkg/c.module.css")));
+        assert!(matcher.is_ignored(&root.join("target/debug/d.module.css")));
+        assert!(matcher.is_ignored(&root.join("other-target/e.module.css")));
+        assert!(matcher.is_ignored(&root.join("src/../node_modules/pkg/c.module.css")));
+        assert!(!matcher.is_ignored(&root.join("node_modules/../src/a.module.css")));
+    }
+
+    #[test]
+    fn test_ignore_files_above_git_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("repo/.git")).unwrap();
+        fs::create_dir_all(root.join("repo/src")).unwrap();
+        fs::write(root.join("repo/src/a.module.css"), ".a { color: red; }").unwrap();
+        fs::write(root.join("repo/src/old.module.css"), ".a { color: red; }").unwrap();
+        fs::write(root.join(".gitignore"), "*.module.css\n").unwrap();
+        fs::write(root.join("repo/.gitignore"), "/src/old.module.css\n").unwrap();
+
+        let config = Config::from_partials(
+            root.to_path_buf(),
+            PartialConfig {
+                folders: Some(vec![PathBuf::from("repo")]),
+                ..Default::default()
+            },
+            None,
+        )
+        .unwrap();
+
+        // Discovery and the watcher filter stop at the same folder, the ignore files outside
+        // of the repository don't apply to either.
+        assert_eq!(
+            find_css_modules(&config),
+            [root.join("repo/src/a.module.css")]
+        );
+        let mut matcher = IgnoreMatcher::default();
+        assert!(!matcher.is_ignored(&root.join("repo/src/a.module.css")));
+        assert!(matcher.is_ignored(&root.join("repo/src/old.module.css")));
+    }
+}
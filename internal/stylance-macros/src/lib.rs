@@ -2,8 +2,8 @@ use std::{env, path::Path};
 
 use anyhow::Context as _;
 use proc_macro::TokenStream;
-use proc_macro2::{Ident, Span};
-use quote::{quote, quote_spanned};
+use proc_macro2::Span;
+use quote::quote;
 use stylance_core::Config;
 use syn::{parse_macro_input, LitStr};
 
@@ -13,27 +13,21 @@ fn try_import_style_classes_with_path(
     identifier_span: Span,
 ) -> anyhow::Result<TokenStream> {
     let config = Config::load(manifest_path.to_path_buf())?;
-    let (_, classes) = stylance_core::get_classes(file_path, &config)?;
+    let classes = stylance_core::get_classes(file_path, &config)?;
 
     let binding = file_path.canonicalize().unwrap();
     let full_path = binding.to_string_lossy();
 
-    let identifiers = classes
-        .iter()
-        .map(|class| Ident::new(&class.original_name.replace('-', "_"), identifier_span))
-        .collect::<Vec<_>>();
+    let dependency_paths = classes.dependencies.iter().map(|p| p.to_string_lossy());
 
-    let output_fields = classes.iter().zip(identifiers).map(|(class, class_ident)| {
-        let class_str = &class.hashed_name;
-        quote_spanned!(identifier_span =>
-            #[allow(non_upper_case_globals)]
-            pub const #class_ident: &str = #class_str;
-        )
-    });
+    let items = stylance_core::codegen::module_items(&classes, identifier_span);
+    let registration = stylance_core::codegen::registration(file_path, &config, &classes)?;
 
     Ok(quote! {
         const _ : &[u8] = include_bytes!(#full_path);
-        #(#output_fields )*
+        #(const _ : &[u8] = include_bytes!(#dependency_paths);)*
+        #items
+        #registration
     }
     .into())
 }
@@ -53,7 +47,7 @@ pub fn import_style_classes(input: TokenStream) -> TokenStream {
 
     match try_import_style_classes(&input) {
         Ok(ts) => ts,
-        Err(err) => syn::Error::new_spanned(&input, err.to_string())
+        Err(err) => syn::Error::new_spanned(&input, format!("{err:#}"))
             .to_compile_error()
             .into(),
     }
@@ -85,7 +79,7 @@ pub fn import_style_classes_rel(input: TokenStream) -> TokenStream {
 
     match try_import_style_classes_rel(&input) {
         Ok(ts) => ts,
-        Err(err) => syn::Error::new_spanned(&input, err.to_string())
+        Err(err) => syn::Error::new_spanned(&input, format!("{err:#}"))
             .to_compile_error()
             .into(),
     }
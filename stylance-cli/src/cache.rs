@@ -0,0 +1,231 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    hash::{DefaultHasher, Hash as _, Hasher as _},
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use stylance_core::{Config, MinifiedNames, ModifyCssResult};
+
+use crate::parallel_map;
+
+/// Keeps the modified css of each module between runs so that only changed modules get parsed again.
+///
+/// The cache assumes that the config doesn't change, call [`ModuleCache::clear`] when it does.
+#[derive(Default)]
+pub struct ModuleCache {
+    modules: HashMap<PathBuf, CachedModule>,
+    minified_names: MinifiedNames,
+}
+
+struct CachedModule {
+    stamp: FileStamp,
+    /// Files read to resolve `composes ... from`, the module is outdated when any of them changes.
+    dependencies: Vec<(PathBuf, FileStamp)>,
+    result: ModifyCssResult,
+}
+
+/// Identifies a version of a file.
+///
+/// The modification time is checked first, the contents are only hashed again when it changed.
+#[derive(Clone, PartialEq)]
+struct FileStamp {
+    modified: SystemTime,
+    len: u64,
+    content_hash: u64,
+}
+
+impl FileStamp {
+    fn read(path: &Path) -> io::Result<Self> {
+        let meta = fs::metadata(path)?;
+        Ok(Self {
+            modified: meta.modified()?,
+            len: meta.len(),
+            content_hash: content_hash(&fs::read(path)?),
+        })
+    }
+
+    /// Whether the file still has the contents of this stamp.
+    ///
+    /// Updates the modification time when a file was touched without changing its contents.
+    fn refresh(&mut self, path: &Path) -> bool {
+        let Ok(meta) = fs::metadata(path) else {
+            return false;
+        };
+        let Ok(modified) = meta.modified() else {
+            return false;
+        };
+
+        if meta.len() != self.len {
+            return false;
+        }
+        if modified == self.modified {
+            return true;
+        }
+
+        match fs::read(path) {
+            Ok(contents) if content_hash(&contents) == self.content_hash => {
+                self.modified = modified;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl CachedModule {
+    fn load(css_file: &Path, config: &Config) -> anyhow::Result<Self> {
+        // Stamp the file before reading it, a change made while it is processed will
+        // be picked up on the next run.
+        let stamp = FileStamp::read(css_file)?;
+        let result = stylance_core::load_and_modify_css(css_file, config)?;
+        let dependencies = result
+            .dependencies
+            .iter()
+            .map(|path| Ok((path.clone(), FileStamp::read(path)?)))
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self {
+            stamp,
+            dependencies,
+            result,
+        })
+    }
+}
+
+fn content_hash(contents: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl ModuleCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forgets every cached module, needed when the config changes.
+    pub fn clear(&mut self) {
+        self.modules.clear();
+    }
+
+    /// Same as [`stylance_core::load_and_modify_css`] for every file, but reuses the previous
+    /// results of the files that didn't change. The changed files are processed on up to `jobs`
+    /// threads.
+    pub(crate) fn load_and_modify_css_files(
+        &mut self,
+        css_files: &[PathBuf],
+        config: &Config,
+        jobs: usize,
+    ) -> anyhow::Result<Vec<ModifyCssResult>> {
+        let outdated = css_files
+            .iter()
+            .filter(|css_file| !self.refresh(css_file))
+            .collect::<Vec<_>>();
+
+        let loaded = parallel_map(&outdated, jobs, |css_file| {
+            CachedModule::load(css_file, config)
+        });
+
+        let mut error = None;
+        for (css_file, module) in outdated.into_iter().zip(loaded) {
+            match module {
+                Ok(module) => {
+                    self.modules.insert(css_file.clone(), module);
+                }
+                Err(e) => {
+                    self.modules.remove(css_file);
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        if let Some(e) = error {
+            return Err(e);
+        }
+
+        Ok(css_files
+            .iter()
+            .map(|css_file| self.modules[css_file].result.clone())
+            .collect())
+    }
+
+    /// Whether the cached module of a file is still up to date.
+    fn refresh(&mut self, css_file: &Path) -> bool {
+        self.modules.get_mut(css_file).is_some_and(|module| {
+            module.stamp.refresh(css_file)
+                && module
+                    .dependencies
+                    .iter_mut()
+                    .all(|(path, stamp)| stamp.refresh(path))
+        })
+    }
+
+    /// Drops the modules that weren't found anymore, like deleted or renamed files.
+    pub(crate) fn retain(&mut self, css_files: &[PathBuf]) {
+        let css_files = css_files.iter().collect::<HashSet<_>>();
+        self.modules.retain(|path, _| css_files.contains(path));
+    }
+
+    /// Drops every cached module if the minified names changed since the last run.
+    pub(crate) fn set_minified_names(&mut self, minified_names: &MinifiedNames) {
+        if *minified_names != self.minified_names {
+            self.modules.clear();
+            self.minified_names = minified_names.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use stylance_core::PartialConfig;
+
+    use super::*;
+    use crate::load_and_modify_crate_cached;
+
+    #[test]
+    fn test_module_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        fs::create_dir_all(&src).unwrap();
+        let a = src.join("a.module.css");
+        let b = src.join("b.module.css");
+        fs::write(&a, ".a { composes: b from \"./b.module.css\"; }").unwrap();
+        fs::write(&b, ".b { color: red; }").unwrap();
+
+        let config =
+            Config::from_partials(dir.path().to_path_buf(), PartialConfig::default(), None)
+                .unwrap();
+        let mut cache = ModuleCache::new();
+
+        let contents = |cache: &mut ModuleCache| {
+            let mut files = load_and_modify_crate_cached(&config, cache, None)?
+                .into_iter()
+                .map(|f| (f.path.file_name().unwrap().to_owned(), f.contents))
+                .collect::<Vec<_>>();
+            files.sort();
+            anyhow::Ok(files)
+        };
+
+        assert_eq!(contents(&mut cache).unwrap().len(), 2);
+
+        // Unchanged modules are reused, even if the file was written with the same contents.
+        cache.modules.get_mut(&a).unwrap().result.contents = "cached".into();
+        fs::write(&a, ".a { composes: b from \"./b.module.css\"; }").unwrap();
+        assert_eq!(contents(&mut cache).unwrap()[0].1, "cached");
+
+        // A change to a composed module invalidates the modules that compose it.
+        fs::write(&b, ".c { color: red; }").unwrap();
+        assert!(contents(&mut cache).is_err());
+
+        fs::write(&a, ".a { color: blue; }").unwrap();
+        assert!(contents(&mut cache).unwrap()[0]
+            .1
+            .ends_with(" { color: blue; }"));
+
+        fs::remove_file(&b).unwrap();
+        assert_eq!(contents(&mut cache).unwrap().len(), 1);
+        assert_eq!(cache.modules.len(), 1);
+    }
+}
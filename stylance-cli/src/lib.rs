@@ -1,21 +1,118 @@
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
+    env,
     fs::{self, File},
-    io::{BufWriter, Write},
+    io::{self, BufWriter, Write},
+    num::NonZeroUsize,
     path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
 };
 
 use anyhow::bail;
+#[cfg(feature = "lightningcss")]
+use optimize::optimize as optimize_css;
+#[cfg(feature = "sass")]
+use sass::compile as compile_scss;
+use serde::Serialize;
+use source_map::SourceMap;
 pub use stylance_core::Config;
-use stylance_core::{path_utils, ModifyCssResult};
-use walkdir::WalkDir;
+use stylance_core::{
+    find_css_modules, path_utils, Class, MinifiedNames, ModifyCssResult, ParseError,
+};
+
+mod cache;
+#[cfg(feature = "lightningcss")]
+mod optimize;
+#[cfg(feature = "sass")]
+mod sass;
+mod source_map;
+#[cfg(feature = "unused")]
+mod unused;
+
+pub use cache::ModuleCache;
+pub use stylance_core::IgnoreMatcher;
+#[cfg(feature = "unused")]
+pub use unused::{find_unused_classes, UnusedClass};
 
 pub fn run(config: &Config) -> anyhow::Result<()> {
     println!("Running stylance");
     run_silent(config, |file_path| println!("{}", file_path.display()))
 }
 
+/// Bundles the css modules of the crate being built, call it from a build script to
+/// produce the output files during `cargo build` without running the stylance cli.
+///
+/// Prints `cargo:rerun-if-changed` for the configuration, every css module and the css module
+/// folders that don't contain an output. Errors are reported as `cargo:warning` so they don't
+/// fail the build.
+pub fn build_script() {
+    let Some(manifest_dir) = env::var_os("CARGO_MANIFEST_DIR") else {
+        println!("cargo:warning=CARGO_MANIFEST_DIR env var not found, stylance_cli::build_script must be called from build.rs");
+        return;
+    };
+
+    build_script_impl(Path::new(&manifest_dir), |line| println!("{line}"));
+}
+
+fn build_script_impl(manifest_dir: &Path, mut print: impl FnMut(String)) {
+    print(format!(
+        "cargo:rerun-if-changed={}",
+        manifest_dir.join("Cargo.toml").display()
+    ));
+
+    if let Err(e) = bundle_from_build_script(manifest_dir, &mut print) {
+        for line in format!("stylance: {e:#}").lines() {
+            print(format!("cargo:warning={line}"));
+        }
+    }
+}
+
+fn bundle_from_build_script(
+    manifest_dir: &Path,
+    print: &mut impl FnMut(String),
+) -> anyhow::Result<()> {
+    let config = Config::load(manifest_dir.to_path_buf())?;
+    let mut rerun_if_changed =
+        |path: &Path| print(format!("cargo:rerun-if-changed={}", path.display()));
+
+    if let Some(workspace_dir) = &config.workspace_dir {
+        rerun_if_changed(&workspace_dir.join("Cargo.toml"));
+    }
+
+    // New css modules show up as changes to the folders. A folder that contains one of the
+    // outputs would change on every build and rerun the build script forever, only its
+    // modules are watched so new ones are picked up by the next rerun.
+    let outputs = [
+        &config.output_file,
+        &config.output_dir,
+        &config.manifest_file,
+    ]
+    .into_iter()
+    .flatten()
+    .chain(config.minify_names.then_some(&config.minified_names_file))
+    .map(path_utils::clean)
+    .collect::<Vec<_>>();
+    for folder in &config.folders {
+        let clean_folder = path_utils::clean(folder);
+        if !outputs
+            .iter()
+            .any(|output| output.starts_with(&clean_folder))
+        {
+            rerun_if_changed(folder);
+        }
+    }
+
+    // Printed before the modules are processed so that fixing an error reruns the build script.
+    for css_file in find_css_modules(&config) {
+        rerun_if_changed(&path_utils::normalize(css_file)?);
+    }
+
+    let modified_css_files = load_and_modify_crate(&config)?;
+    write_output(&[(&config, &modified_css_files)])
+}
+
 pub fn run_silent(
     config: &Config,
     mut file_visit_callback: impl FnMut(&Path),
@@ -30,29 +127,73 @@ pub fn run_silent(
 }
 
 pub fn load_and_modify_crate(config: &Config) -> anyhow::Result<Vec<ModifyCssResult>> {
-    let mut modified_css_files = Vec::new();
+    modify_crate(config, true, None, jobs(None))
+}
 
-    for folder in config.folders.iter() {
-        for (entry, meta) in WalkDir::new(folder)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter_map(|entry| entry.metadata().ok().map(|meta| (entry, meta)))
-        {
-            if meta.is_file() {
-                let path_str = entry.path().to_string_lossy();
-                if config.extensions.iter().any(|ext| path_str.ends_with(ext)) {
-                    modified_css_files
-                        .push(stylance_core::load_and_modify_css(entry.path(), config)?);
-                }
-            }
+/// Same as [`load_and_modify_crate`] but only parses the modules that changed since the
+/// previous call with the same `cache`.
+///
+/// The modules are processed on up to `jobs` threads, `None` uses the available parallelism.
+pub fn load_and_modify_crate_cached(
+    config: &Config,
+    cache: &mut ModuleCache,
+    jobs: Option<NonZeroUsize>,
+) -> anyhow::Result<Vec<ModifyCssResult>> {
+    modify_crate(config, true, Some(cache), self::jobs(jobs))
+}
+
+/// Same as [`load_and_modify_crate`] but never writes to disk.
+///
+/// When `minify_names` is enabled it fails if the lock file is missing names instead of updating it.
+/// The modules are processed on up to `jobs` threads, `None` uses the available parallelism.
+pub fn check_crate(
+    config: &Config,
+    jobs: Option<NonZeroUsize>,
+) -> anyhow::Result<Vec<ModifyCssResult>> {
+    modify_crate(config, false, None, self::jobs(jobs))
+}
+
+fn modify_crate(
+    config: &Config,
+    update_lock_file: bool,
+    cache: Option<&mut ModuleCache>,
+    jobs: usize,
+) -> anyhow::Result<Vec<ModifyCssResult>> {
+    let mut css_files = find_css_modules(config);
+
+    let config = if config.minify_names {
+        Cow::Owned(allocate_minified_names(
+            config,
+            &mut css_files,
+            update_lock_file,
+        )?)
+    } else {
+        Cow::Borrowed(config)
+    };
+
+    let modified_css_files = match cache {
+        Some(cache) => {
+            cache.retain(&css_files);
+            cache.set_minified_names(&config.minified_names);
+            cache.load_and_modify_css_files(&css_files, &config, jobs)?
         }
-    }
+        None => parallel_map(&css_files, jobs, |css_file| {
+            stylance_core::load_and_modify_css(css_file, &config)
+        })
+        .into_iter()
+        .collect::<anyhow::Result<_>>()?,
+    };
 
     {
-        // Verify that there are no hash collisions
+        // Verify that there are no hash collisions.
+        // Files with identical output are allowed to share a hash, this happens when
+        // hashing by content and two modules have the same contents.
         let mut map = HashMap::new();
         for file in modified_css_files.iter() {
-            if let Some(previous_file) = map.insert(&file.hash, file) {
+            if let Some(previous_file) = map
+                .insert(&file.hash, file)
+                .filter(|previous_file| previous_file.contents != file.contents)
+            {
                 bail!(
                     "The following files had a hash collision:\n{}\n{}\nConsider increasing the hash_len setting.",
                     file.path.to_string_lossy(),
@@ -65,24 +206,222 @@ pub fn load_and_modify_crate(config: &Config) -> anyhow::Result<Vec<ModifyCssRes
     Ok(modified_css_files)
 }
 
+/// Number of threads used to process the css modules of a crate.
+fn jobs(jobs: Option<NonZeroUsize>) -> usize {
+    jobs.or_else(|| thread::available_parallelism().ok())
+        .map_or(1, NonZeroUsize::get)
+}
+
+/// Maps the items on up to `jobs` threads, the results keep the order of the items.
+fn parallel_map<T, R>(items: &[T], jobs: usize, f: impl Fn(&T) -> R + Sync) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+{
+    let jobs = jobs.min(items.len());
+    if jobs <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut results = items.iter().map(|_| None).collect::<Vec<_>>();
+    thread::scope(|scope| {
+        let workers = (0..jobs)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut processed = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(index) else {
+                            break processed;
+                        };
+                        processed.push((index, f(item)));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for worker in workers {
+            for (index, result) in worker.join().expect("worker thread panicked") {
+                results[index] = Some(result);
+            }
+        }
+    });
+
+    results
+        .into_iter()
+        .map(|result| result.expect("every item should be processed"))
+        .collect()
+}
+
+/// Allocates minified names for every scoped name of the crate and updates the lock file.
+/// The global class names of the crate are reserved first so that no scoped name uses them.
+///
+/// Files are processed in path order so that the allocation doesn't depend on the
+/// order in which the filesystem lists them.
+fn allocate_minified_names(
+    config: &Config,
+    css_files: &mut [PathBuf],
+    update_lock_file: bool,
+) -> anyhow::Result<Config> {
+    // Always reload the lock file, another crate of the workspace may have updated it.
+    let mut minified_names = MinifiedNames::load(&config.minified_names_file)?;
+
+    css_files.sort();
+    for css_file in css_files.iter() {
+        for name in stylance_core::global_class_names(css_file, config)? {
+            minified_names.reserve(&name);
+        }
+    }
+    for css_file in css_files.iter() {
+        stylance_core::allocate_minified_names(css_file, config, &mut minified_names)?;
+    }
+
+    if minified_names.is_modified() {
+        if !update_lock_file {
+            bail!(
+                "{} is out of date",
+                config.minified_names_file.to_string_lossy()
+            );
+        }
+        minified_names.save(&config.minified_names_file)?;
+    }
+
+    Ok(Config {
+        minified_names,
+        ..config.clone()
+    })
+}
+
 pub fn write_output(crates: &[(&Config, &[ModifyCssResult])]) -> anyhow::Result<()> {
-    let mut output_files = HashMap::<PathBuf, Vec<Cow<str>>>::new();
+    render_output(crates)?.write()
+}
 
-    // Clear the output dir of all crates.
-    for &(config, _) in crates {
-        if let Some(output_dir) = &config.output_dir {
-            let output_dir = output_dir.join("stylance");
-            fs::create_dir_all(&output_dir)?;
+#[cfg(not(feature = "lightningcss"))]
+fn optimize_css(
+    _bundle: &str,
+    _parts: &[(std::ops::Range<usize>, &ModifyCssResult)],
+    _minify: bool,
+    _targets: &[String],
+) -> anyhow::Result<String> {
+    bail!("minify and targets require stylance-cli to be built with the `lightningcss` feature")
+}
 
-            let entries = fs::read_dir(&output_dir)?;
+/// Column of a position within a line, 0 based and counted in the unit of the tool that
+/// reported it.
+#[cfg_attr(not(all(feature = "sass", feature = "lightningcss")), allow(dead_code))]
+#[derive(Clone, Copy)]
+enum Column {
+    /// Counted in characters, used by grass.
+    Chars(usize),
+    /// Counted in UTF-16 code units, used by lightningcss.
+    Utf16(usize),
+}
 
-            for entry in entries {
-                let entry = entry?;
-                let file_type = entry.file_type()?;
+#[cfg_attr(not(any(feature = "sass", feature = "lightningcss")), allow(dead_code))]
+impl Column {
+    /// Byte offset of the column within `line`.
+    fn byte_offset(self, line: &str) -> usize {
+        let (column, units): (usize, fn(char) -> usize) = match self {
+            Column::Chars(column) => (column, |_| 1),
+            Column::Utf16(column) => (column, char::len_utf16),
+        };
 
-                if file_type.is_file() {
-                    fs::remove_file(entry.path())?;
-                }
+        let mut count = 0;
+        for (offset, c) in line.char_indices() {
+            if count >= column {
+                return offset;
+            }
+            count += units(c);
+        }
+        line.len()
+    }
+}
+
+/// Creates an error for a position of a bundled output file.
+///
+/// When the position is inside of a css module the error points to the original module file.
+#[cfg_attr(not(any(feature = "sass", feature = "lightningcss")), allow(dead_code))]
+fn bundle_error(
+    bundle: &str,
+    parts: &[(std::ops::Range<usize>, &ModifyCssResult)],
+    line: usize,
+    column: Column,
+    message: String,
+) -> anyhow::Error {
+    let mut lines = bundle.split_inclusive('\n');
+    let line_start = lines.by_ref().take(line).map(str::len).sum::<usize>();
+    let offset = line_start + column.byte_offset(lines.next().unwrap_or_default());
+
+    match parts.iter().find(|(range, _)| range.contains(&offset)) {
+        Some((range, module)) => anyhow::Error::new(ParseError::new(
+            &module.path,
+            &module.original_contents,
+            module.original_offset(offset - range.start),
+            message,
+        )),
+        None => anyhow::anyhow!("{message}"),
+    }
+}
+
+#[cfg(not(feature = "sass"))]
+fn compile_scss(
+    _bundle: &str,
+    _parts: &[(std::ops::Range<usize>, &ModifyCssResult)],
+    _load_paths: &[PathBuf],
+) -> anyhow::Result<String> {
+    bail!("compile_scss requires stylance-cli to be built with the `sass` feature")
+}
+
+/// Parts that are concatenated to produce an output file.
+#[derive(Default)]
+struct OutputFile<'a> {
+    /// Contents of each part, along with the css module it comes from.
+    parts: Vec<(Cow<'a, str>, Option<&'a ModifyCssResult>)>,
+    /// Whether a source map should be generated next to the file.
+    source_map: bool,
+    /// Whether the file should be compiled from scss to css.
+    compile_scss: bool,
+    scss_load_paths: Vec<PathBuf>,
+    minify: bool,
+    /// Browserslist queries used to add vendor prefixes.
+    targets: Vec<String>,
+}
+
+/// Contents of the `manifest_file`.
+#[derive(Serialize)]
+struct Manifest<'a> {
+    modules: Vec<ManifestModule<'a>>,
+}
+
+#[derive(Serialize)]
+struct ManifestModule<'a> {
+    /// Path of the module relative to `hash_root_path`, with `/` separators.
+    path: String,
+    hash: &'a str,
+    classes: &'a [Class],
+}
+
+/// The files that stylance generates for a set of crates, see [`render_output`].
+pub struct Output {
+    /// The "stylance" folders inside of each output_dir.
+    /// Files inside of them that aren't generated are stale and get removed.
+    generated_dirs: Vec<PathBuf>,
+    files: BTreeMap<PathBuf, String>,
+}
+
+/// Computes the output files of a set of crates without writing them.
+pub fn render_output(crates: &[(&Config, &[ModifyCssResult])]) -> anyhow::Result<Output> {
+    let mut generated_dirs = Vec::new();
+    let mut output_files = BTreeMap::<PathBuf, OutputFile>::new();
+    let mut files_contents = BTreeMap::new();
+    let mut manifests = BTreeMap::<PathBuf, Vec<ManifestModule>>::new();
+
+    for &(config, _) in crates {
+        if let Some(output_dir) = &config.output_dir {
+            let output_dir = path_utils::normalize(output_dir.join("stylance"))?;
+            if !generated_dirs.contains(&output_dir) {
+                generated_dirs.push(output_dir);
             }
         }
     }
@@ -100,26 +439,64 @@ pub fn write_output(crates: &[(&Config, &[ModifyCssResult])]) -> anyhow::Result<
             files.sort_unstable_by(|a, b| key(a).cmp(&key(b)));
         }
 
+        if let Some(manifest_file) = &config.manifest_file {
+            manifests
+                .entry(path_utils::normalize(manifest_file)?)
+                .or_default()
+                .extend(files.iter().map(|f| {
+                    ManifestModule {
+                        path: f
+                            .relative_path
+                            .components()
+                            .map(|c| c.as_os_str().to_string_lossy())
+                            .collect::<Vec<_>>()
+                            .join("/"),
+                        hash: &f.hash,
+                        classes: &f.classes,
+                    }
+                }));
+        }
+
         if let Some(output_file) = &config.output_file {
             let outputs = output_files
                 .entry(path_utils::normalize(output_file)?)
                 .or_default();
+            outputs.source_map |= config.source_map;
+            outputs.compile_scss |= config.compile_scss;
+            outputs.minify |= config.minify;
+            for target in &config.targets {
+                if !outputs.targets.contains(target) {
+                    outputs.targets.push(target.clone());
+                }
+            }
+            for load_path in &config.scss_load_paths {
+                if !outputs.scss_load_paths.contains(load_path) {
+                    outputs.scss_load_paths.push(load_path.clone());
+                }
+            }
 
             if let Some(scss_prelude) = &config.scss_prelude {
-                if output_file
-                    .extension()
-                    .filter(|ext| ext.to_string_lossy() == "scss")
-                    .is_some()
+                if config.compile_scss
+                    || output_file
+                        .extension()
+                        .filter(|ext| ext.to_string_lossy() == "scss")
+                        .is_some()
                 {
-                    outputs.push(Cow::Borrowed(scss_prelude.as_str()));
+                    outputs
+                        .parts
+                        .push((Cow::Borrowed(scss_prelude.as_str()), None));
                 }
             }
 
-            outputs.extend(files.iter().map(|f| Cow::Borrowed(f.contents.as_str())));
+            outputs.parts.extend(
+                files
+                    .iter()
+                    .map(|f| (Cow::Borrowed(f.contents.as_str()), Some(*f))),
+            );
         }
 
         if let Some(output_dir) = &config.output_dir {
-            let output_dir = output_dir.join("stylance");
+            let output_dir = path_utils::normalize(output_dir.join("stylance"))?;
             let mut new_files = Vec::new();
             for modified_css in files {
                 let extension = modified_css
@@ -141,48 +518,266 @@ pub fn write_output(crates: &[(&Config, &[ModifyCssResult])]) -> anyhow::Result<
 
                 new_files.push(new_file_name.clone());
 
-                let file_path = output_dir.join(new_file_name);
-                let mut file = BufWriter::new(File::create(file_path)?);
-
+                let mut contents = String::new();
                 if let Some(scss_prelude) = &config.scss_prelude {
                     if extension == "scss" {
-                        file.write_all(scss_prelude.as_bytes())?;
-                        file.write_all(b"\n\n")?;
+                        contents.push_str(scss_prelude);
+                        contents.push_str("\n\n");
                     }
                 }
+                contents.push_str(&modified_css.contents);
 
-                file.write_all(modified_css.contents.as_bytes())?;
+                files_contents.insert(output_dir.join(new_file_name), contents);
             }
 
-            let index_path = output_dir.join("_index.scss");
-
             let outputs = output_files
-                .entry(path_utils::normalize(index_path)?)
+                .entry(output_dir.join("_index.scss"))
                 .or_default();
-            outputs.push(Cow::Owned(
-                new_files
-                    .iter()
-                    .map(|f| format!("@use \"{f}\";"))
-                    .collect::<Vec<_>>()
-                    .join("\n"),
+            outputs.parts.push((
+                Cow::Owned(
+                    new_files
+                        .iter()
+                        .map(|f| format!("@use \"{f}\";"))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                ),
+                None,
             ));
         }
     }
 
-    for (output_file, files) in output_files {
-        if let Some(parent) = output_file.parent() {
-            fs::create_dir_all(parent)?;
+    for (path, output_file) in output_files {
+        let file_name = path
+            .file_name()
+            .expect("output file should have a name")
+            .to_string_lossy()
+            .into_owned();
+        let map_dir = path.parent().expect("output file should have a parent dir");
+        let mut source_map = output_file
+            .source_map
+            .then(|| SourceMap::new(file_name.clone()));
+
+        let mut contents = String::new();
+        let mut line = 0;
+        let mut module_ranges = Vec::new();
+        for (i, (part, module)) in output_file.parts.iter().enumerate() {
+            if i > 0 {
+                contents.push_str("\n\n");
+                line += 2;
+            }
+            if let Some(module) = module {
+                module_ranges.push((contents.len()..contents.len() + part.len(), *module));
+                if let Some(source_map) = &mut source_map {
+                    source_map.add_module(map_dir, module, line)?;
+                }
+            }
+            contents.push_str(part);
+            line += part.matches('\n').count();
+        }
+
+        if output_file.compile_scss {
+            if source_map.is_some() {
+                bail!("source_map can't be used together with compile_scss");
+            }
+
+            // Relative imports of the bundle are resolved from the output file's folder.
+            let mut load_paths = vec![map_dir.to_path_buf()];
+            load_paths.extend(output_file.scss_load_paths.iter().cloned());
+            contents = compile_scss(&contents, &module_ranges, &load_paths)?;
+            // The compiled css can't be mapped back to the modules anymore.
+            module_ranges.clear();
+        }
+
+        if output_file.minify || !output_file.targets.is_empty() {
+            if source_map.is_some() {
+                bail!("source_map can't be used together with minify or targets");
+            }
+            if !output_file.compile_scss && path.extension().is_some_and(|ext| ext == "scss") {
+                bail!(
+                    "minify and targets need a css output, use a .css output_file or enable compile_scss"
+                );
+            }
+
+            contents = optimize_css(
+                &contents,
+                &module_ranges,
+                output_file.minify,
+                &output_file.targets,
+            )?;
         }
 
-        let mut file = BufWriter::new(File::create(output_file)?);
-        file.write_all(files.join("\n\n").as_bytes())?;
+        if let Some(source_map) = source_map {
+            let map_path = path.with_file_name(format!("{file_name}.map"));
+            contents.push_str(&format!("\n/*# sourceMappingURL={file_name}.map */\n"));
+            files_contents.insert(map_path, source_map.to_json()?);
+        }
+
+        files_contents.insert(path, contents);
+    }
+
+    for (path, mut modules) in manifests {
+        modules.sort_by(|a, b| a.path.cmp(&b.path));
+        let mut contents = serde_json::to_string_pretty(&Manifest { modules })?;
+        contents.push('\n');
+        files_contents.insert(path, contents);
     }
 
-    Ok(())
+    Ok(Output {
+        generated_dirs,
+        files: files_contents,
+    })
+}
+
+impl Output {
+    pub fn write(&self) -> anyhow::Result<()> {
+        // Clear the output dir of all crates.
+        for output_dir in &self.generated_dirs {
+            fs::create_dir_all(output_dir)?;
+
+            for entry in fs::read_dir(output_dir)? {
+                let entry = entry?;
+                if entry.file_type()?.is_file() {
+                    fs::remove_file(entry.path())?;
+                }
+            }
+        }
+
+        for (path, contents) in &self.files {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            let mut file = BufWriter::new(File::create(path)?);
+            file.write_all(contents.as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the generated contents of an output file, `path` must be normalized.
+    pub fn get(&self, path: &Path) -> Option<&str> {
+        self.files.get(path).map(|contents| contents.as_str())
+    }
+
+    /// Returns the files on disk that don't match the generated output.
+    ///
+    /// This includes missing or outdated output files as well as leftover
+    /// files inside of the generated "stylance" folders.
+    pub fn stale_files(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let mut stale = Vec::new();
+
+        for (path, contents) in &self.files {
+            match fs::read(path) {
+                Ok(on_disk) if on_disk == contents.as_bytes() => {}
+                Ok(_) => stale.push(path.clone()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => stale.push(path.clone()),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        for output_dir in &self.generated_dirs {
+            let entries = match fs::read_dir(output_dir) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+
+            for entry in entries {
+                let entry = entry?;
+                if entry.file_type()?.is_file() && !self.files.contains_key(&entry.path()) {
+                    stale.push(entry.path());
+                }
+            }
+        }
+
+        stale.sort();
+        Ok(stale)
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    #[test]
+    fn test_build_script() {
+        use super::*;
+
+        let dir = tempfile::tempdir().unwrap();
+        let css_file = dir.path().join("src/style.module.css");
+        fs::create_dir_all(css_file.parent().unwrap()).unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nname = \"app\"\n\n[package.metadata.stylance]\noutput_file = \"bundle.css\"\n",
+        )
+        .unwrap();
+        fs::write(&css_file, ".a { color: red; }").unwrap();
+
+        let run = || {
+            let mut lines = Vec::new();
+            build_script_impl(dir.path(), |line| lines.push(line));
+            lines
+        };
+
+        let lines = run();
+        let rerun = |path: &Path| format!("cargo:rerun-if-changed={}", path.display());
+        assert!(lines.contains(&rerun(&dir.path().join("Cargo.toml"))));
+        assert!(lines.contains(&rerun(&dir.path().join("./src/"))));
+        assert!(lines.contains(&rerun(&css_file)));
+        assert!(!lines.iter().any(|l| l.starts_with("cargo:warning")));
+        assert!(fs::read_to_string(dir.path().join("bundle.css"))
+            .unwrap()
+            .starts_with(".a-"));
+
+        // Errors don't fail the build, they are reported as warnings.
+        fs::write(&css_file, ".a { color: red;").unwrap();
+        let lines = run();
+        assert!(lines.contains(&rerun(&dir.path().join("./src/"))));
+        assert!(lines
+            .iter()
+            .any(|l| l.starts_with("cargo:warning=stylance: ")));
+    }
+
+    #[test]
+    fn test_build_script_output_in_folder() {
+        use super::*;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(dir.join("styles")).unwrap();
+        fs::write(
+            dir.join("Cargo.toml"),
+            "[package]\nname = \"app\"\n\n[package.metadata.stylance]\noutput_file = \"src/bundle.css\"\nfolders = [\"src\", \"styles\"]\n",
+        )
+        .unwrap();
+        fs::write(dir.join("src/style.module.css"), ".a { color: red; }").unwrap();
+        fs::write(dir.join("styles/other.module.css"), ".b { color: red; }").unwrap();
+
+        let mut lines = Vec::new();
+        build_script_impl(dir, |line| lines.push(line));
+
+        // Watching `src` would rerun the build script after every write of the bundle.
+        let rerun = |path: &Path| format!("cargo:rerun-if-changed={}", path.display());
+        assert!(!lines.contains(&rerun(&dir.join("src"))));
+        assert!(lines.contains(&rerun(&dir.join("styles"))));
+        assert!(lines.contains(&rerun(&dir.join("src/style.module.css"))));
+        assert!(!lines.iter().any(|l| l.starts_with("cargo:warning")));
+        assert!(dir.join("src/bundle.css").is_file());
+    }
+
+    #[test]
+    fn test_parallel_map() {
+        use super::*;
+
+        let items = (0..100).collect::<Vec<_>>();
+        for jobs in [1, 3, 8, 200] {
+            assert_eq!(
+                parallel_map(&items, jobs, |i| i * 2),
+                items.iter().map(|i| i * 2).collect::<Vec<_>>()
+            );
+        }
+        assert!(parallel_map(&[] as &[i32], 4, |i| *i).is_empty());
+    }
+
     #[cfg(unix)]
     #[test]
     fn test_symlinked_folder() {
@@ -236,4 +831,262 @@ mod tests {
             "output should contain original CSS body, got: {output}"
         );
     }
+
+    #[test]
+    fn test_content_hash_collisions() {
+        use super::*;
+        use stylance_core::{HashStrategy, PartialConfig};
+
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        fs::create_dir_all(src.join("other")).unwrap();
+        fs::write(src.join("a.module.css"), ".a { color: red; }").unwrap();
+        fs::write(src.join("other/a.module.css"), ".a { color: red; }").unwrap();
+
+        let config = |hash_len| {
+            Config::from_partials(
+                dir.path().to_path_buf(),
+                PartialConfig {
+                    hash_strategy: Some(HashStrategy::Content),
+                    hash_len: Some(hash_len),
+                    ..Default::default()
+                },
+                None,
+            )
+            .unwrap()
+        };
+
+        // Identical files share the same hash and output, that is not a collision.
+        let files = load_and_modify_crate(&config(7)).expect("identical files should not collide");
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].hash, files[1].hash);
+
+        // Different contents with colliding (empty) hashes are still reported.
+        fs::write(src.join("other/a.module.css"), ".a { color: blue; }").unwrap();
+        assert!(load_and_modify_crate(&config(0)).is_err());
+    }
+
+    #[test]
+    fn test_minify_names() {
+        use super::*;
+        use stylance_core::PartialConfig;
+
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join("a.module.css"), ".header { color: red; }").unwrap();
+        fs::write(
+            src.join("b.module.css"),
+            ".header { color: blue; }\n.footer { color: green; }",
+        )
+        .unwrap();
+
+        let config = || {
+            Config::from_partials(
+                dir.path().to_path_buf(),
+                PartialConfig {
+                    minify_names: Some(true),
+                    ..Default::default()
+                },
+                None,
+            )
+            .unwrap()
+        };
+
+        // The proc macros can't allocate names, they need the cli to run first.
+        assert!(stylance_core::get_classes(&src.join("a.module.css"), &config()).is_err());
+
+        let files = load_and_modify_crate(&config()).unwrap();
+        let contents = files
+            .iter()
+            .map(|f| f.contents.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            contents,
+            [
+                ".a { color: red; }",
+                ".b { color: blue; }\n.c { color: green; }"
+            ]
+        );
+        assert!(dir.path().join("stylance.lock").exists());
+
+        // A new module doesn't change the names that were already allocated.
+        fs::write(src.join("0.module.css"), ".main { color: red; }").unwrap();
+        let files = load_and_modify_crate(&config()).unwrap();
+        assert_eq!(files[0].contents, ".d { color: red; }");
+        assert_eq!(files[1].contents, ".a { color: red; }");
+
+        let classes = stylance_core::get_classes(&src.join("b.module.css"), &config()).unwrap();
+        let names = classes
+            .classes
+            .iter()
+            .map(|c| (c.original_name.as_str(), c.hashed_name.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(names, [("footer", "c"), ("header", "b")]);
+        assert!(classes
+            .dependencies
+            .contains(&dir.path().join("stylance.lock")));
+    }
+
+    #[test]
+    fn test_minify_names_skip_global_classes() {
+        use super::*;
+        use stylance_core::PartialConfig;
+
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join("a.module.css"), ".header { color: red; }").unwrap();
+
+        let config = || {
+            Config::from_partials(
+                dir.path().to_path_buf(),
+                PartialConfig {
+                    minify_names: Some(true),
+                    ..Default::default()
+                },
+                None,
+            )
+            .unwrap()
+        };
+
+        let files = load_and_modify_crate(&config()).unwrap();
+        assert_eq!(files[0].contents, ".a { color: red; }");
+
+        // Global classes of any module of the crate move the scoped names using them.
+        fs::write(
+            src.join("b.module.css"),
+            ":global(.a) .footer { color: blue; }\n:global .b .c { color: green; }",
+        )
+        .unwrap();
+        let files = load_and_modify_crate(&config()).unwrap();
+        assert_eq!(files[0].contents, ".d { color: red; }");
+        assert_eq!(
+            files[1].contents,
+            ".a .e { color: blue; }\n .b .c { color: green; }"
+        );
+    }
+
+    #[cfg(feature = "sass")]
+    #[test]
+    fn test_compile_scss() {
+        use super::*;
+        use stylance_core::{ParseError, PartialConfig};
+
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        let styles = dir.path().join("styles");
+        fs::create_dir_all(&src).unwrap();
+        fs::create_dir_all(&styles).unwrap();
+        fs::write(styles.join("prelude.scss"), "$primary: red;").unwrap();
+        fs::write(
+            src.join("card.module.scss"),
+            ".card {\n  color: $primary;\n  .title { font-weight: bold; }\n}\n",
+        )
+        .unwrap();
+
+        let config = Config::from_partials(
+            dir.path().to_path_buf(),
+            PartialConfig {
+                output_file: Some("./out.css".into()),
+                compile_scss: Some(true),
+                scss_load_paths: Some(vec!["./styles".into()]),
+                scss_prelude: Some("@use \"prelude\" as *;".into()),
+                ..Default::default()
+            },
+            None,
+        )
+        .unwrap();
+
+        run_silent(&config, |_| {}).unwrap();
+        let files = load_and_modify_crate(&config).unwrap();
+        let hash = &files[0].hash;
+        assert_eq!(
+            fs::read_to_string(dir.path().join("out.css")).unwrap(),
+            format!(
+                ".card-{hash} {{\n  color: red;\n}}\n.card-{hash} .title-{hash} {{\n  font-weight: bold;\n}}\n"
+            )
+        );
+
+        // Compile errors point to the original module file.
+        fs::write(
+            src.join("card.module.scss"),
+            ".card {\n  color: $missing;\n}\n",
+        )
+        .unwrap();
+        let err = run_silent(&config, |_| {}).unwrap_err();
+        let err = err
+            .downcast_ref::<ParseError>()
+            .expect("error should be a ParseError");
+        assert_eq!(
+            err.path,
+            path_utils::normalize(src.join("card.module.scss")).unwrap()
+        );
+        assert_eq!((err.line, err.column), (2, 10));
+
+        // Columns are counted in characters, not bytes.
+        fs::write(
+            src.join("card.module.scss"),
+            ".card {\n  /* ünïcödé */ color: $missing;\n}\n",
+        )
+        .unwrap();
+        let err = run_silent(&config, |_| {}).unwrap_err();
+        let err = err
+            .downcast_ref::<ParseError>()
+            .expect("error should be a ParseError");
+        assert_eq!((err.line, err.column), (2, 24));
+    }
+
+    #[cfg(feature = "lightningcss")]
+    #[test]
+    fn test_minify_and_targets() {
+        use super::*;
+        use stylance_core::PartialConfig;
+
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(
+            src.join("card.module.css"),
+            ".card {\n  user-select: none;\n}\n.card { color: #ff0000; }\n",
+        )
+        .unwrap();
+
+        let config = |output_file: &str| {
+            Config::from_partials(
+                dir.path().to_path_buf(),
+                PartialConfig {
+                    output_file: Some(output_file.into()),
+                    minify: Some(true),
+                    targets: Some(vec!["safari 10".into()]),
+                    ..Default::default()
+                },
+                None,
+            )
+            .unwrap()
+        };
+
+        let config_css = config("./out.css");
+        run_silent(&config_css, |_| {}).unwrap();
+        let hash = &load_and_modify_crate(&config_css).unwrap()[0].hash;
+        assert_eq!(
+            fs::read_to_string(dir.path().join("out.css")).unwrap(),
+            format!(".card-{hash}{{-webkit-user-select:none;user-select:none;color:red}}")
+        );
+
+        // Scss output can't be processed unless it is compiled first.
+        assert!(run_silent(&config("./out.scss"), |_| {}).is_err());
+
+        // Columns are reported in UTF-16 units, the error still points to the right character.
+        fs::write(
+            src.join("card.module.css"),
+            ".card { content: \"ü𝄞\"; } .b > { }\n",
+        )
+        .unwrap();
+        let err = run_silent(&config_css, |_| {}).unwrap_err();
+        let err = err
+            .downcast_ref::<stylance_core::ParseError>()
+            .expect("error should be a ParseError");
+        assert_eq!((err.line, err.column), (1, 31));
+    }
 }
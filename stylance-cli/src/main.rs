@@ -1,24 +1,74 @@
 use std::{
     collections::HashMap,
     env,
+    num::NonZeroUsize,
     path::{Path, PathBuf},
+    process::ExitCode,
     sync::Arc,
     time::Duration,
 };
-use stylance_cli::{load_and_modify_crate, write_output};
+use stylance_cli::{
+    check_crate, find_unused_classes, load_and_modify_crate_cached, render_output, IgnoreMatcher,
+    ModuleCache,
+};
 use stylance_core::{path_utils, Config, ModifyCssResult};
 
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
 use notify::{Event, RecursiveMode, Watcher};
 use tokio::{
-    sync::mpsc,
+    sync::{mpsc, watch},
     task::JoinSet,
     time::{sleep, Instant},
 };
 
+mod serve;
+
+use serve::Stylesheet;
+
 #[derive(Parser)]
-#[command(author, version, about, long_about = None, arg_required_else_help = true)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    arg_required_else_help = true,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    crates: CrateArgs,
+
+    /// Watch the fylesystem for changes to the css module files
+    #[arg(short, long)]
+    watch: bool,
+
+    /// Start a live reload server on this port that pushes the output file to the browser after every change
+    #[arg(long, value_name = "PORT", requires = "watch")]
+    serve: Option<u16>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check the css modules and verify that the output files are up to date, without writing anything.
+    ///
+    /// Exits with a non-zero code when a css module has errors or when the output files
+    /// are stale, which is useful to catch forgotten regenerations in CI.
+    Check(CrateArgs),
+
+    /// List the classes of imported css modules that aren't referenced from rust code.
+    ///
+    /// Scans the rust files of each crate for `import_style!` and `import_crate_style!`
+    /// invocations and for references to the generated class constants.
+    /// Exits with a non-zero code when unused classes are found.
+    Unused(CrateArgs),
+}
+
+#[derive(Args)]
+struct CrateArgs {
     /// The path(s) where your crate's Cargo toml is located.
     /// Multiple paths can be specified to process several crates at once.
     #[arg(required = true)]
@@ -32,38 +82,161 @@ struct Cli {
     #[arg(long)]
     output_dir: Option<PathBuf>,
 
+    /// Generate a source map next to the output file
+    #[arg(long)]
+    source_map: bool,
+
+    /// Minify the output file
+    #[arg(long)]
+    minify: bool,
+
+    /// Generate a JSON file mapping the classes of every css module to their scoped names
+    #[arg(long)]
+    manifest: Option<PathBuf>,
+
     /// The folders in your crate where stylance will look for css modules
     ///
     /// The paths are relative to the manifest_dir and must not land outside of manifest_dir.
     #[arg(short, long, num_args(1))]
     folder: Vec<PathBuf>,
 
-    /// Watch the fylesystem for changes to the css module files
+    /// Number of threads used to process the css modules, defaults to the number of cpus
     #[arg(short, long)]
-    watch: bool,
+    jobs: Option<NonZeroUsize>,
 }
 
 // We are using tokio mainly for the ease of implementing debouncing and cancellation.
 // It is alright to call io blocking functions in async functions of this app.
 
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> anyhow::Result<()> {
+async fn main() -> ExitCode {
     let cli = Cli::parse();
+    match &cli.command {
+        Some(Command::Check(args)) => return check(args),
+        Some(Command::Unused(args)) => return unused(args),
+        None => {}
+    }
+
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn check(args: &CrateArgs) -> ExitCode {
+    let mut success = true;
+
+    let mut crates = Vec::new();
+    for manifest_dir in &args.manifest_dirs {
+        let result = load_config(args, manifest_dir)
+            .and_then(|config| check_crate(&config, args.jobs).map(|files| (config, files)));
+
+        match result {
+            Ok((config, files)) => crates.push((config, files)),
+            Err(e) => {
+                eprintln!("error: {e:#}");
+                success = false;
+            }
+        }
+    }
+
+    // Output files can be shared between crates, so they can only be checked if every crate loaded.
+    if success {
+        let stale_files = render_output(
+            &crates
+                .iter()
+                .map(|(config, files)| (config, files.as_slice()))
+                .collect::<Vec<_>>(),
+        )
+        .and_then(|output| output.stale_files());
+
+        match stale_files {
+            Ok(stale_files) if stale_files.is_empty() => {}
+            Ok(stale_files) => {
+                eprintln!("error: the following output files are out of date, run stylance to regenerate them:");
+                let cwd = env::current_dir().unwrap_or_default();
+                for file in stale_files {
+                    let file = path_utils::diff_normalized_paths(&file, &cwd).unwrap_or(file);
+                    eprintln!("    {}", file.to_string_lossy());
+                }
+                success = false;
+            }
+            Err(e) => {
+                eprintln!("error: {e:#}");
+                success = false;
+            }
+        }
+    }
+
+    if success {
+        println!("Stylance output is up to date");
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn unused(args: &CrateArgs) -> ExitCode {
+    let mut success = true;
+    let cwd = env::current_dir().unwrap_or_default();
+
+    for manifest_dir in &args.manifest_dirs {
+        let unused_classes =
+            load_config(args, manifest_dir).and_then(|config| find_unused_classes(&config));
+
+        match unused_classes {
+            Ok(unused_classes) => {
+                for class in &unused_classes {
+                    let css_file = path_utils::diff_normalized_paths(&class.css_file, &cwd)
+                        .unwrap_or_else(|_| class.css_file.clone());
+                    println!(
+                        "{}:{}:{}: unused class `{}`",
+                        css_file.to_string_lossy(),
+                        class.line,
+                        class.column,
+                        class.name
+                    );
+                }
+                success &= unused_classes.is_empty();
+            }
+            Err(e) => {
+                eprintln!("error: {e:#}");
+                success = false;
+            }
+        }
+    }
+
+    if success {
+        println!("No unused classes found");
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+async fn run(cli: Cli) -> anyhow::Result<()> {
+    let listener = match cli.serve {
+        Some(port) => Some(serve::bind(port).await?),
+        None => None,
+    };
 
     let mut crate_states = Vec::new();
-    for manifest_dir in &cli.manifest_dirs {
-        let config = Arc::new(load_config(&cli, manifest_dir)?);
-        let files = load_and_modify_crate(&config)?;
+    for manifest_dir in &cli.crates.manifest_dirs {
+        let config = Arc::new(load_config(&cli.crates, manifest_dir)?);
+        let mut cache = ModuleCache::new();
+        let files = load_and_modify_crate_cached(&config, &mut cache, cli.crates.jobs)?;
         print_files(&files)?;
-        crate_states.push(CrateState { config, files });
+        crate_states.push(CrateState {
+            config,
+            files,
+            cache,
+        });
     }
 
-    write_output(
-        &crate_states
-            .iter()
-            .map(|c| (&*c.config, c.files.as_slice()))
-            .collect::<Vec<_>>(),
-    )?;
+    let stylesheets = write_output(&crate_states, listener.is_some())?;
 
     if cli.watch {
         let cli = Arc::new(cli);
@@ -79,7 +252,20 @@ async fn main() -> anyhow::Result<()> {
             set.spawn(watch_single(cli, config.clone(), crate_idx, tx.clone()));
         }
 
-        set.spawn(watch_crates(crate_states, rx));
+        let live_reload = match listener {
+            Some(listener) => {
+                println!(
+                    "Live reload server listening on http://{}/events",
+                    listener.local_addr()?
+                );
+                let (stylesheets_tx, stylesheets_rx) = watch::channel(stylesheets);
+                set.spawn(serve::serve(listener, stylesheets_rx));
+                Some(stylesheets_tx)
+            }
+            None => None,
+        };
+
+        set.spawn(watch_crates(crate_states, rx, live_reload, cli.crates.jobs));
 
         // If any watcher ends (only happens on error), abort the rest and exit.
         if let Some(result) = set.join_next().await {
@@ -91,6 +277,26 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Writes the output files of all crates.
+///
+/// Returns the stylesheets to push to the browser when `live_reload` is enabled.
+fn write_output(states: &[CrateState], live_reload: bool) -> anyhow::Result<Vec<Stylesheet>> {
+    let configs = states.iter().map(|c| &*c.config).collect::<Vec<_>>();
+    let output = render_output(
+        &states
+            .iter()
+            .map(|c| (&*c.config, c.files.as_slice()))
+            .collect::<Vec<_>>(),
+    )?;
+    output.write()?;
+
+    if live_reload {
+        serve::stylesheets(&configs, &output)
+    } else {
+        Ok(Vec::new())
+    }
+}
+
 fn print_files(files: &[ModifyCssResult]) -> anyhow::Result<()> {
     let cwd = env::current_dir()?;
     for file in files {
@@ -102,11 +308,14 @@ fn print_files(files: &[ModifyCssResult]) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn load_config(cli: &Cli, manifest_dir: &Path) -> anyhow::Result<Config> {
+fn load_config(cli: &CrateArgs, manifest_dir: &Path) -> anyhow::Result<Config> {
     let mut config = Config::load(manifest_dir.to_owned())?;
 
     config.output_file = cli.output_file.clone().or(config.output_file);
     config.output_dir = cli.output_dir.clone().or(config.output_dir);
+    config.manifest_file = cli.manifest.clone().or(config.manifest_file);
+    config.source_map |= cli.source_map;
+    config.minify |= cli.minify;
 
     if !cli.folder.is_empty() {
         config.folders = cli.folder.iter().map(|p| manifest_dir.join(p)).collect();
@@ -182,6 +391,8 @@ fn watch_folders(paths: &[PathBuf]) -> anyhow::Result<mpsc::UnboundedReceiver<Pa
 struct CrateState {
     config: Arc<Config>,
     files: Vec<ModifyCssResult>,
+    /// Modules of the previous build, only the files that changed get parsed again.
+    cache: ModuleCache,
 }
 
 const DEBOUNCE_DURATION: Duration = Duration::from_millis(50);
@@ -189,6 +400,8 @@ const DEBOUNCE_DURATION: Duration = Duration::from_millis(50);
 async fn watch_crates(
     mut states: Vec<CrateState>,
     mut rx: tokio::sync::mpsc::UnboundedReceiver<(usize, Arc<Config>)>,
+    live_reload: Option<watch::Sender<Vec<Stylesheet>>>,
+    jobs: Option<NonZeroUsize>,
 ) -> anyhow::Result<()> {
     while let Some((idx, config)) = rx.recv().await {
         // Debounce logic:
@@ -224,26 +437,28 @@ async fn watch_crates(
         // Debounce ended, received contains 1 or more crates.
 
         for (idx, config) in received {
-            match load_and_modify_crate(&config) {
+            let state = &mut states[idx];
+            if !Arc::ptr_eq(&state.config, &config) {
+                // The config was reloaded, every module has to be processed again.
+                state.cache.clear();
+            }
+
+            match load_and_modify_crate_cached(&config, &mut state.cache, jobs) {
                 Ok(modified) => {
                     print_files(&modified)?;
-                    states[idx] = CrateState {
-                        config: config.clone(),
-                        files: modified,
-                    };
+                    state.config = config;
+                    state.files = modified;
                 }
                 Err(e) => {
-                    eprintln!("{e}");
+                    eprintln!("error: {e:#}");
                 }
             };
         }
 
-        write_output(
-            &states
-                .iter()
-                .map(|c| (&*c.config, c.files.as_slice()))
-                .collect::<Vec<_>>(),
-        )?;
+        let stylesheets = write_output(&states, live_reload.is_some())?;
+        if let Some(live_reload) = &live_reload {
+            live_reload.send_replace(stylesheets);
+        }
     }
 
     Ok(())
@@ -272,14 +487,15 @@ async fn watch_single(
         // Watch the folders from the current config
         let mut folder_events = watch_folders(&config.folders)?;
 
-        // With the events from the watched folder trigger run_events if they match the extensions of the config.
+        // With the events from the watched folder trigger run_events if they are css modules of the config.
+        // Events from ignored files (like copies inside of the target dir) are skipped.
         let watch_folders_fut = {
             let build_tx = build_tx.clone();
             let config = config.clone();
             async move {
+                let mut ignore_matcher = IgnoreMatcher::default();
                 while let Some(path) = folder_events.recv().await {
-                    let str_path = path.to_string_lossy();
-                    if config.extensions.iter().any(|ext| str_path.ends_with(ext)) {
+                    if config.is_css_module(&path) && !ignore_matcher.is_ignored(&path) {
                         let _ = build_tx.send((crate_idx, config));
                         break;
                     }
@@ -296,13 +512,11 @@ async fn watch_single(
         // The cargo_toml_watcher triggered so wait a bit and reload the config.
         tokio::time::sleep(Duration::from_millis(50)).await;
 
-        match load_config(&cli, &config.manifest_dir) {
+        match load_config(&cli.crates, &config.manifest_dir) {
             Ok(new_config) => {
                 config = Arc::new(new_config);
             }
-            Err(e) => {
-                eprintln!("{e}")
-            }
+            Err(e) => eprintln!("error: {e:#}"),
         }
 
         // trigger a rebuild
@@ -0,0 +1,57 @@
+use std::ops::Range;
+
+use anyhow::{anyhow, Context as _};
+use lightningcss::{
+    stylesheet::{MinifyOptions, ParserOptions, PrinterOptions, StyleSheet},
+    targets::{Browsers, Targets},
+};
+use stylance_core::ModifyCssResult;
+
+use crate::{bundle_error, Column};
+
+/// Minifies a bundled css output file and adds the vendor prefixes needed by `targets`.
+///
+/// `parts` are the byte ranges of the bundle that come from each css module, they are
+/// used to report parse errors against the original module file.
+pub fn optimize(
+    bundle: &str,
+    parts: &[(Range<usize>, &ModifyCssResult)],
+    minify: bool,
+    targets: &[String],
+) -> anyhow::Result<String> {
+    let targets = Targets::from(
+        Browsers::from_browserslist(targets)
+            .with_context(|| format!("Invalid targets {targets:?}"))?,
+    );
+
+    let mut stylesheet = StyleSheet::parse(bundle, ParserOptions::default()).map_err(|e| {
+        let message = e.kind.to_string();
+        match e.loc {
+            Some(loc) => bundle_error(
+                bundle,
+                parts,
+                loc.line as usize,
+                Column::Utf16(loc.column.saturating_sub(1) as usize),
+                message,
+            ),
+            None => anyhow!("Failed to parse css: {message}"),
+        }
+    })?;
+
+    stylesheet
+        .minify(MinifyOptions {
+            targets,
+            ..Default::default()
+        })
+        .map_err(|e| anyhow!("Failed to minify css: {e}"))?;
+
+    let result = stylesheet
+        .to_css(PrinterOptions {
+            minify,
+            targets,
+            ..Default::default()
+        })
+        .map_err(|e| anyhow!("Failed to print css: {e}"))?;
+
+    Ok(result.code)
+}
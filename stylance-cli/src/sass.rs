@@ -0,0 +1,36 @@
+use std::{ops::Range, path::PathBuf};
+
+use anyhow::anyhow;
+use stylance_core::ModifyCssResult;
+
+use crate::{bundle_error, Column};
+
+/// Compiles a bundled scss output file to css.
+///
+/// `parts` are the byte ranges of the bundle that come from each css module, they are
+/// used to report compile errors against the original module file.
+pub fn compile(
+    bundle: &str,
+    parts: &[(Range<usize>, &ModifyCssResult)],
+    load_paths: &[PathBuf],
+) -> anyhow::Result<String> {
+    let options = grass::Options::default()
+        .load_paths(load_paths)
+        .input_syntax(grass::InputSyntax::Scss);
+
+    grass::from_string(bundle, &options).map_err(|e| {
+        let display = e.to_string();
+        match (*e).kind() {
+            grass::ErrorKind::ParseError { message, loc, .. } if loc.file.name() == "stdin" => {
+                bundle_error(
+                    bundle,
+                    parts,
+                    loc.begin.line,
+                    Column::Chars(loc.begin.column),
+                    message,
+                )
+            }
+            _ => anyhow!("Failed to compile scss: {display}"),
+        }
+    })
+}
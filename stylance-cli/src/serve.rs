@@ -0,0 +1,165 @@
+use std::path::PathBuf;
+
+use anyhow::Context as _;
+use serde::Serialize;
+use stylance_cli::Output;
+use stylance_core::{path_utils, Config};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::watch,
+};
+
+/// A bundled output file pushed to the browser when it changes.
+#[derive(Serialize, Clone, PartialEq)]
+pub struct Stylesheet {
+    /// File name of the output file, used by the browser to find the stylesheet to replace.
+    file: String,
+    css: String,
+}
+
+/// Collects the output files of the crates that the browser can hot swap.
+pub fn stylesheets(config: &[&Config], output: &Output) -> anyhow::Result<Vec<Stylesheet>> {
+    let mut paths = Vec::<PathBuf>::new();
+    for config in config {
+        if let Some(output_file) = &config.output_file {
+            let output_file = path_utils::normalize(output_file)?;
+            if !paths.contains(&output_file) {
+                paths.push(output_file);
+            }
+        }
+    }
+
+    Ok(paths
+        .iter()
+        .filter_map(|path| {
+            Some(Stylesheet {
+                file: path.file_name()?.to_string_lossy().into_owned(),
+                css: output.get(path)?.to_owned(),
+            })
+        })
+        .collect())
+}
+
+/// Listens on `127.0.0.1` only, the address the live reload script connects to.
+pub async fn bind(port: u16) -> anyhow::Result<TcpListener> {
+    TcpListener::bind(("127.0.0.1", port))
+        .await
+        .with_context(|| format!("Failed to start the live reload server on port {port}"))
+}
+
+/// Serves a Server-Sent Events stream on `/events` that sends a `css` event with every
+/// changed stylesheet.
+pub async fn serve(
+    listener: TcpListener,
+    stylesheets: watch::Receiver<Vec<Stylesheet>>,
+) -> anyhow::Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let stylesheets = stylesheets.clone();
+        tokio::spawn(async move {
+            // Errors only mean that the browser went away.
+            let _ = handle_connection(stream, stylesheets).await;
+        });
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    mut stylesheets: watch::Receiver<Vec<Stylesheet>>,
+) -> std::io::Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 || request.len() > 16 * 1024 {
+            return Ok(());
+        }
+        request.extend_from_slice(&buf[..read]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    if request_path(&request) != Some("/events") {
+        stream
+            .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .await?;
+        return Ok(());
+    }
+
+    stream
+        .write_all(
+            concat!(
+                "HTTP/1.1 200 OK\r\n",
+                "Content-Type: text/event-stream\r\n",
+                "Cache-Control: no-cache\r\n",
+                "Access-Control-Allow-Origin: *\r\n",
+                "\r\n",
+            )
+            .as_bytes(),
+        )
+        .await?;
+
+    // The page loaded the current output already, only changes are sent.
+    let mut previous = stylesheets.borrow_and_update().clone();
+    while stylesheets.changed().await.is_ok() {
+        let current = stylesheets.borrow_and_update().clone();
+        let changed = current
+            .iter()
+            .filter(|s| !previous.contains(s))
+            .collect::<Vec<_>>();
+
+        stream.write_all(events(&changed).as_bytes()).await?;
+        previous = current;
+    }
+
+    Ok(())
+}
+
+/// Returns the path of an http request without the query string.
+fn request_path(request: &str) -> Option<&str> {
+    let mut request_line = request.lines().next()?.split(' ');
+    if request_line.next()? != "GET" {
+        return None;
+    }
+    let target = request_line.next()?;
+    Some(target.split_once('?').map_or(target, |(path, _)| path))
+}
+
+fn events(stylesheets: &[&Stylesheet]) -> String {
+    stylesheets
+        .iter()
+        .map(|stylesheet| {
+            let data = serde_json::to_string(stylesheet).expect("stylesheet should serialize");
+            format!("event: css\ndata: {data}\n\n")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_path() {
+        assert_eq!(
+            request_path("GET /events?t=1 HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+            Some("/events")
+        );
+        assert_eq!(request_path("GET / HTTP/1.1\r\n\r\n"), Some("/"));
+        assert_eq!(request_path("POST /events HTTP/1.1\r\n\r\n"), None);
+        assert_eq!(request_path(""), None);
+    }
+
+    #[test]
+    fn test_events() {
+        let stylesheet = Stylesheet {
+            file: "bundle.css".into(),
+            css: ".a-1 {\n\tcolor: red;\n}\n".into(),
+        };
+
+        assert_eq!(
+            events(&[&stylesheet]),
+            "event: css\ndata: {\"file\":\"bundle.css\",\"css\":\".a-1 {\\n\\tcolor: red;\\n}\\n\"}\n\n"
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::Serialize;
+use stylance_core::{path_utils, ModifyCssResult};
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// A Source Map v3 for a bundled output file.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceMap {
+    version: u32,
+    file: String,
+    sources: Vec<String>,
+    sources_content: Vec<String>,
+    names: Vec<String>,
+    mappings: String,
+    #[serde(skip)]
+    name_indices: HashMap<String, i64>,
+    #[serde(skip)]
+    state: EncoderState,
+}
+
+/// Previous values of the segment fields, segments are encoded relative to them.
+#[derive(Default)]
+struct EncoderState {
+    generated_line: usize,
+    generated_column: i64,
+    source: i64,
+    original_line: i64,
+    original_column: i64,
+    name: i64,
+}
+
+impl SourceMap {
+    pub fn new(file: String) -> Self {
+        Self {
+            version: 3,
+            file,
+            sources: Vec::new(),
+            sources_content: Vec::new(),
+            names: Vec::new(),
+            mappings: String::new(),
+            name_indices: HashMap::new(),
+            state: EncoderState::default(),
+        }
+    }
+
+    /// Adds the mappings of a css module whose contents start at `generated_line` of the
+    /// output file. Modules must be added in order.
+    pub fn add_module(
+        &mut self,
+        map_dir: &Path,
+        module: &ModifyCssResult,
+        generated_line: usize,
+    ) -> anyhow::Result<()> {
+        let source = path_utils::diff_normalized_paths(&module.path, map_dir)?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let source_index = self.sources.len() as i64;
+        self.sources.push(source);
+        self.sources_content.push(module.original_contents.clone());
+
+        let generated = LineIndex::new(&module.contents);
+        let original = LineIndex::new(&module.original_contents);
+
+        for mapping in &module.mappings {
+            let (line, column) = generated.position(mapping.generated);
+            let (original_line, original_column) = original.position(mapping.original);
+            let name = mapping.name.as_ref().map(|name| self.name_index(name));
+
+            self.add_segment(
+                generated_line + line,
+                column,
+                source_index,
+                original_line as i64,
+                original_column,
+                name,
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    fn name_index(&mut self, name: &str) -> i64 {
+        if let Some(index) = self.name_indices.get(name) {
+            return *index;
+        }
+        let index = self.names.len() as i64;
+        self.names.push(name.to_owned());
+        self.name_indices.insert(name.to_owned(), index);
+        index
+    }
+
+    fn add_segment(
+        &mut self,
+        generated_line: usize,
+        generated_column: i64,
+        source: i64,
+        original_line: i64,
+        original_column: i64,
+        name: Option<i64>,
+    ) {
+        let state = &mut self.state;
+
+        if generated_line > state.generated_line || self.mappings.is_empty() {
+            for _ in state.generated_line..generated_line {
+                self.mappings.push(';');
+            }
+            state.generated_line = generated_line;
+            state.generated_column = 0;
+        } else if !self.mappings.ends_with(';') {
+            self.mappings.push(',');
+        }
+
+        encode_vlq(
+            &mut self.mappings,
+            generated_column - state.generated_column,
+        );
+        encode_vlq(&mut self.mappings, source - state.source);
+        encode_vlq(&mut self.mappings, original_line - state.original_line);
+        encode_vlq(&mut self.mappings, original_column - state.original_column);
+        if let Some(name) = name {
+            encode_vlq(&mut self.mappings, name - state.name);
+            state.name = name;
+        }
+
+        state.generated_column = generated_column;
+        state.source = source;
+        state.original_line = original_line;
+        state.original_column = original_column;
+    }
+}
+
+/// Converts byte offsets into zero based line and column numbers.
+///
+/// Columns are counted in UTF-16 code units as browsers expect.
+struct LineIndex<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            line_starts: std::iter::once(0)
+                .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+                .collect(),
+        }
+    }
+
+    fn position(&self, offset: usize) -> (usize, i64) {
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let column = self.text[self.line_starts[line]..offset]
+            .encode_utf16()
+            .count();
+        (line, column as i64)
+    }
+}
+
+fn encode_vlq(out: &mut String, value: i64) {
+    let mut value = if value < 0 {
+        ((-value) << 1) | 1
+    } else {
+        value << 1
+    };
+
+    loop {
+        let mut digit = value & 0b11111;
+        value >>= 5;
+        if value > 0 {
+            digit |= 0b100000;
+        }
+        out.push(BASE64[digit as usize] as char);
+        if value == 0 {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_vlq() {
+        let encode = |value| {
+            let mut out = String::new();
+            encode_vlq(&mut out, value);
+            out
+        };
+
+        assert_eq!(encode(0), "A");
+        assert_eq!(encode(1), "C");
+        assert_eq!(encode(-1), "D");
+        assert_eq!(encode(15), "e");
+        assert_eq!(encode(16), "gB");
+        assert_eq!(encode(-123), "3H");
+    }
+
+    #[test]
+    fn test_line_index() {
+        let index = LineIndex::new("a\néb\n\nc");
+        assert_eq!(index.position(0), (0, 0));
+        assert_eq!(index.position(2), (1, 0));
+        assert_eq!(index.position(4), (1, 1));
+        assert_eq!(index.position(6), (2, 0));
+        assert_eq!(index.position(7), (3, 0));
+    }
+}
@@ -0,0 +1,655 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context as _};
+use proc_macro2::{Delimiter, Span, TokenStream, TokenTree};
+use stylance_core::{codegen, path_utils, Class, Config};
+use syn::{
+    ext::IdentExt,
+    parse::{Parse, ParseStream},
+    visit::Visit,
+    Attribute, Ident, LitStr, Token, UseTree, Visibility,
+};
+
+/// Folders of a crate that contain rust sources.
+const RUST_FOLDERS: &[&str] = &["src", "tests", "examples", "benches"];
+
+/// A class of an imported css module whose constant is never referenced from rust code.
+pub struct UnusedClass {
+    pub css_file: PathBuf,
+    pub name: String,
+    /// 1 based line number.
+    pub line: usize,
+    /// 1 based column number.
+    pub column: usize,
+}
+
+/// Path of a rust module, starting with `crate`.
+type ModulePath = Vec<String>;
+
+/// An `import_style!` or `import_crate_style!` invocation.
+struct StyleImport {
+    /// Rust file of the invocation.
+    rust_file: PathBuf,
+    /// Module the invocation is declared in.
+    parent: ModulePath,
+    module: String,
+    css_file: PathBuf,
+}
+
+impl StyleImport {
+    /// Path of the generated module.
+    fn module_path(&self) -> ModulePath {
+        let mut path = self.parent.clone();
+        path.push(self.module.clone());
+        path
+    }
+}
+
+/// Arguments of the import macros: `[#[attribute]] [pub] module_identifier, style_path`
+struct ImportArgs {
+    module: Ident,
+    path: LitStr,
+}
+
+impl Parse for ImportArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Attribute::parse_outer(input)?;
+        input.parse::<Visibility>()?;
+        let module = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let path = input.parse()?;
+        Ok(Self { module, path })
+    }
+}
+
+/// A rust source file and the paths it references.
+struct RustFile {
+    path: PathBuf,
+    /// Module of the file, inline `mod` blocks inside of it aren't tracked.
+    module_path: ModulePath,
+    /// Names brought into scope with `use`, with the paths they can refer to.
+    uses: HashMap<String, Vec<ModulePath>>,
+    /// Modules whose items are brought into scope with `use module::*`.
+    glob_uses: Vec<ModulePath>,
+    references: References,
+}
+
+impl RustFile {
+    /// Absolute module paths that a path written in this file can refer to.
+    ///
+    /// Paths that don't start with `crate`, `self` or `super` are ambiguous without resolving
+    /// the whole crate, every reasonable interpretation is returned.
+    fn resolve(&self, path: &[String]) -> Vec<ModulePath> {
+        let Some((first, rest)) = path.split_first() else {
+            return Vec::new();
+        };
+        let join = |base: &[String], rest: &[String]| [base, rest].concat();
+
+        match first.as_str() {
+            "crate" => vec![path.to_vec()],
+            "self" => vec![join(&self.module_path, rest)],
+            "super" => {
+                let supers = path.iter().take_while(|s| *s == "super").count();
+                let depth = self.module_path.len().saturating_sub(supers).max(1);
+                vec![join(&self.module_path[..depth], &path[supers..])]
+            }
+            name => {
+                let mut resolved = vec![
+                    join(&self.module_path, path),
+                    join(&["crate".to_owned()], path),
+                    // The first segment can be the name of the crate, from tests and examples.
+                    join(&["crate".to_owned()], rest),
+                ];
+                for target in self.uses.get(name).into_iter().flatten() {
+                    resolved.push(join(target, rest));
+                }
+                for module in &self.glob_uses {
+                    resolved.push(join(module, path));
+                }
+                resolved
+            }
+        }
+    }
+
+    /// Whether `path` written in this file refers to the module generated by `import`.
+    fn refers_to(&self, path: &[String], import: &StyleImport) -> bool {
+        if let [module] = path {
+            if *module == import.module && self.path == import.rust_file {
+                return true;
+            }
+        }
+        self.resolve(path).contains(&import.module_path())
+    }
+}
+
+/// Collects the style imports and `use` declarations of a rust file.
+struct FileVisitor<'a> {
+    rust_file: &'a Path,
+    manifest_dir: &'a Path,
+    /// Module of the item being visited.
+    module_path: ModulePath,
+    imports: Vec<StyleImport>,
+    /// `(name, path)` of each name brought into scope with `use`, `*` for globs.
+    uses: Vec<(String, Vec<String>)>,
+}
+
+impl FileVisitor<'_> {
+    fn collect_use(&mut self, prefix: &mut Vec<String>, tree: &UseTree) {
+        match tree {
+            UseTree::Path(path) => {
+                prefix.push(path.ident.unraw().to_string());
+                self.collect_use(prefix, &path.tree);
+                prefix.pop();
+            }
+            UseTree::Name(name) => {
+                let mut path = prefix.clone();
+                path.push(name.ident.unraw().to_string());
+                self.uses.push((name.ident.unraw().to_string(), path));
+            }
+            UseTree::Rename(rename) => {
+                let mut path = prefix.clone();
+                path.push(rename.ident.unraw().to_string());
+                self.uses.push((rename.rename.unraw().to_string(), path));
+            }
+            UseTree::Glob(_) => self.uses.push(("*".to_owned(), prefix.clone())),
+            UseTree::Group(group) => {
+                for tree in &group.items {
+                    self.collect_use(prefix, tree);
+                }
+            }
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for FileVisitor<'_> {
+    fn visit_item_mod(&mut self, item: &'ast syn::ItemMod) {
+        self.module_path.push(item.ident.unraw().to_string());
+        syn::visit::visit_item_mod(self, item);
+        self.module_path.pop();
+    }
+
+    fn visit_item_use(&mut self, item: &'ast syn::ItemUse) {
+        self.collect_use(&mut Vec::new(), &item.tree);
+    }
+
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        let base_dir = match mac.path.segments.last() {
+            Some(segment) if segment.ident == "import_style" => self
+                .rust_file
+                .parent()
+                .expect("rust file should have a parent dir"),
+            Some(segment) if segment.ident == "import_crate_style" => self.manifest_dir,
+            _ => return syn::visit::visit_macro(self, mac),
+        };
+
+        // Invocations that don't use a string literal path can't be resolved, skip them.
+        if let Ok(args) = mac.parse_body::<ImportArgs>() {
+            self.imports.push(StyleImport {
+                rust_file: self.rust_file.to_owned(),
+                parent: self.module_path.clone(),
+                module: args.module.unraw().to_string(),
+                css_file: base_dir.join(args.path.value()),
+            });
+        }
+    }
+}
+
+/// Module path of a rust file from its location in one of the [`RUST_FOLDERS`].
+///
+/// `src/lib.rs`, `src/main.rs`, `src/bin/*.rs` and the files directly inside of the other
+/// folders are crate roots, as well as the `main.rs` of the targets that have their own folder.
+fn module_path(folder: &str, folder_relative: &Path) -> ModulePath {
+    let mut path = vec!["crate".to_owned()];
+    let mut components = folder_relative
+        .iter()
+        .map(|c| c.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    // Binaries in `src/bin` are laid out like the targets in the other folders.
+    let mut is_src = folder == "src";
+    if is_src && components.first().is_some_and(|c| c == "bin") {
+        components.remove(0);
+        is_src = false;
+    }
+
+    let Some((file, folders)) = components.split_last() else {
+        return path;
+    };
+    let stem = file.strip_suffix(".rs").unwrap_or(file);
+    let is_root = match folders {
+        [] => !is_src || stem == "lib" || stem == "main",
+        [_] => stem == "main",
+        _ => false,
+    };
+    if is_root {
+        return path;
+    }
+
+    path.extend(folders.iter().cloned());
+    if stem != "mod" {
+        path.push(stem.to_owned());
+    }
+    path
+}
+
+/// What a path found in rust code refers to inside of a module.
+#[derive(Debug, PartialEq)]
+enum Item {
+    Name(String),
+    /// `module::*`
+    Glob,
+}
+
+/// Paths of the form `module::item` found in rust code.
+#[derive(Default)]
+struct References {
+    /// The module part of each path, and the item it refers to.
+    paths: Vec<(Vec<String>, Item)>,
+}
+
+impl References {
+    /// Scans a token stream, including the contents of macro invocations, for paths.
+    fn collect(&mut self, tokens: TokenStream) {
+        self.collect_with_prefix(&[], tokens);
+    }
+
+    /// Scans the tokens that come after `prefix::`, like the contents of `use prefix::{...}`.
+    ///
+    /// When the prefix isn't empty single identifiers are references too.
+    fn collect_with_prefix(&mut self, prefix: &[String], tokens: TokenStream) {
+        let tokens = tokens.into_iter().collect::<Vec<_>>();
+        let is_path_separator = |i: usize| {
+            matches!(
+                (tokens.get(i), tokens.get(i + 1)),
+                (Some(TokenTree::Punct(p1)), Some(TokenTree::Punct(p2)))
+                    if p1.as_char() == ':' && p2.as_char() == ':'
+            )
+        };
+
+        let mut i = 0;
+        while i < tokens.len() {
+            let ident = match &tokens[i] {
+                TokenTree::Group(group) => {
+                    self.collect(group.stream());
+                    i += 1;
+                    continue;
+                }
+                TokenTree::Ident(ident) => ident,
+                TokenTree::Punct(_) | TokenTree::Literal(_) => {
+                    i += 1;
+                    continue;
+                }
+            };
+
+            let mut path = prefix.to_vec();
+            path.push(ident.unraw().to_string());
+            i += 1;
+
+            let mut ends_with_item = true;
+            while is_path_separator(i) {
+                match tokens.get(i + 2) {
+                    Some(TokenTree::Ident(next)) => {
+                        path.push(next.unraw().to_string());
+                        i += 3;
+                    }
+                    // module::{a, b}
+                    Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace => {
+                        self.collect_with_prefix(&path, group.stream());
+                        ends_with_item = false;
+                        i += 3;
+                        break;
+                    }
+                    Some(TokenTree::Punct(p)) if p.as_char() == '*' => {
+                        self.paths.push((path.clone(), Item::Glob));
+                        ends_with_item = false;
+                        i += 3;
+                        break;
+                    }
+                    _ => break,
+                }
+            }
+
+            if ends_with_item && path.len() >= 2 {
+                let item = path.pop().expect("path has at least two segments");
+                self.paths.push((path, Item::Name(item)));
+            }
+        }
+    }
+}
+
+/// Finds the classes of the css modules imported by a crate that aren't referenced from its rust code.
+///
+/// References are resolved against the module where each import is declared, so modules
+/// with the same name in different files are told apart. Classes composed by a referenced
+/// class count as referenced, since they are part of its value.
+pub fn find_unused_classes(config: &Config) -> anyhow::Result<Vec<UnusedClass>> {
+    let mut imports = Vec::new();
+    let mut rust_files = Vec::new();
+
+    for folder_name in RUST_FOLDERS {
+        let folder = config.manifest_dir.join(folder_name);
+        for path in stylance_core::walk_files(&folder)
+            .filter(|path| path.extension().is_some_and(|ext| ext == "rs"))
+        {
+            let rust_file = path.as_path();
+            let source = fs::read_to_string(rust_file)
+                .with_context(|| format!("Failed to read {}", rust_file.to_string_lossy()))?;
+
+            let file = syn::parse_file(&source).with_context(|| {
+                format!("Failed to parse rust file {}", rust_file.to_string_lossy())
+            })?;
+            let module_path = module_path(
+                folder_name,
+                rust_file
+                    .strip_prefix(&folder)
+                    .expect("walked files are inside of the folder"),
+            );
+            let mut visitor = FileVisitor {
+                rust_file,
+                manifest_dir: &config.manifest_dir,
+                module_path: module_path.clone(),
+                imports: Vec::new(),
+                uses: Vec::new(),
+            };
+            visitor.visit_file(&file);
+            imports.extend(visitor.imports);
+
+            let mut references = References::default();
+            references.collect(source.parse().map_err(|e| {
+                anyhow!(
+                    "Failed to parse rust file {}: {e}",
+                    rust_file.to_string_lossy()
+                )
+            })?);
+
+            let mut rust_file = RustFile {
+                path: rust_file.to_owned(),
+                module_path,
+                uses: HashMap::new(),
+                glob_uses: Vec::new(),
+                references,
+            };
+            // `use` paths are resolved without the other `use` declarations of the file.
+            for (name, path) in visitor.uses {
+                let resolved = rust_file.resolve(&path);
+                if name == "*" {
+                    rust_file.glob_uses.extend(resolved);
+                } else {
+                    rust_file.uses.entry(name).or_default().extend(resolved);
+                }
+            }
+            rust_files.push(rust_file);
+        }
+    }
+
+    let mut classes = ModuleClasses::new(config);
+    let mut used = HashSet::<(PathBuf, String)>::new();
+    let mut imported = Vec::new();
+    for import in &imports {
+        let css_file = path_utils::normalize(&import.css_file)?;
+        imported.push(css_file.clone());
+
+        // Mark the referenced classes along with every class they compose.
+        for class in classes.get(&css_file)?.to_vec() {
+            // Identifiers are compared without their `r#` prefix, like `style::r#type`.
+            let constant = codegen::ident(&class.original_name, Span::call_site())
+                .unraw()
+                .to_string();
+            let referenced = rust_files.iter().any(|rust_file| {
+                rust_file.references.paths.iter().any(|(path, item)| {
+                    (*item == Item::Glob || *item == Item::Name(constant.clone()))
+                        && rust_file.refers_to(path, import)
+                })
+            });
+            if referenced {
+                used.insert((css_file.clone(), class.original_name.clone()));
+                for composed in &class.composes {
+                    if let Some(composed) = classes.find_composed(&css_file, composed)? {
+                        used.insert(composed);
+                    }
+                }
+            }
+        }
+    }
+    imported.sort();
+    imported.dedup();
+
+    let mut unused = Vec::new();
+    for css_file in &imported {
+        let unused_names = classes
+            .get(css_file)?
+            .iter()
+            .filter(|class| !used.contains(&(css_file.clone(), class.original_name.clone())))
+            .map(|class| class.original_name.clone())
+            .collect::<HashSet<_>>();
+        if unused_names.is_empty() {
+            continue;
+        }
+
+        for location in stylance_core::class_locations(css_file, config)? {
+            if unused_names.contains(&location.name) {
+                unused.push(UnusedClass {
+                    css_file: css_file.clone(),
+                    name: location.name,
+                    line: location.line,
+                    column: location.column,
+                });
+            }
+        }
+    }
+
+    unused.sort_by(|a, b| (&a.css_file, a.line, a.column).cmp(&(&b.css_file, b.line, b.column)));
+    Ok(unused)
+}
+
+/// Classes of the css modules, loaded once per file.
+struct ModuleClasses<'a> {
+    config: &'a Config,
+    modules: HashMap<PathBuf, (Vec<Class>, Vec<PathBuf>)>,
+}
+
+impl<'a> ModuleClasses<'a> {
+    fn new(config: &'a Config) -> Self {
+        Self {
+            config,
+            modules: HashMap::new(),
+        }
+    }
+
+    fn load(&mut self, css_file: &Path) -> anyhow::Result<&(Vec<Class>, Vec<PathBuf>)> {
+        if !self.modules.contains_key(css_file) {
+            let result = stylance_core::get_classes(css_file, self.config)?;
+            let dependencies = result
+                .dependencies
+                .into_iter()
+                .filter(|path| *path != self.config.minified_names_file)
+                .collect();
+            self.modules
+                .insert(css_file.to_owned(), (result.classes, dependencies));
+        }
+        Ok(&self.modules[css_file])
+    }
+
+    fn get(&mut self, css_file: &Path) -> anyhow::Result<&[Class]> {
+        Ok(&self.load(css_file)?.0)
+    }
+
+    /// Finds the `(css_file, class)` of a scoped name composed by a class of `css_file`.
+    ///
+    /// Looking in the module itself and then in the modules it composes from keeps modules
+    /// with the same hash, like identical files with the content hash strategy, apart.
+    fn find_composed(
+        &mut self,
+        css_file: &Path,
+        hashed_name: &str,
+    ) -> anyhow::Result<Option<(PathBuf, String)>> {
+        let dependencies = self.load(css_file)?.1.clone();
+        for module in std::iter::once(css_file.to_owned()).chain(dependencies) {
+            if let Some(class) = self
+                .get(&module)?
+                .iter()
+                .find(|class| class.hashed_name == hashed_name)
+            {
+                return Ok(Some((module, class.original_name.clone())));
+            }
+        }
+        // Global classes
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use stylance_core::{HashStrategy, PartialConfig};
+
+    use super::*;
+
+    fn strings(path: &[&str]) -> Vec<String> {
+        path.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_references() {
+        let mut references = References::default();
+        references.collect(
+            r#"
+            use style::{header, footer};
+            use other::*;
+            fn view() {
+                html! { <div class={style::main_content}>{ format!("{}", crate::a::style2::title) }</div> }
+            }
+            "#
+            .parse()
+            .unwrap(),
+        );
+
+        let name = |path: &[&str], item: &str| (strings(path), Item::Name(item.to_owned()));
+        assert_eq!(
+            references.paths,
+            [
+                name(&["style"], "header"),
+                name(&["style"], "footer"),
+                (strings(&["other"]), Item::Glob),
+                name(&["style"], "main_content"),
+                name(&["crate", "a", "style2"], "title"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_module_path() {
+        let path = |folder, file| module_path(folder, Path::new(file));
+        assert_eq!(path("src", "lib.rs"), ["crate"]);
+        assert_eq!(path("src", "bin/tool.rs"), ["crate"]);
+        assert_eq!(path("src", "bin/tool/main.rs"), ["crate"]);
+        assert_eq!(path("src", "card.rs"), ["crate", "card"]);
+        assert_eq!(path("src", "a/b.rs"), ["crate", "a", "b"]);
+        assert_eq!(path("src", "a/mod.rs"), ["crate", "a"]);
+        assert_eq!(path("tests", "integration.rs"), ["crate"]);
+        assert_eq!(path("tests", "common/mod.rs"), ["crate", "common"]);
+    }
+
+    #[test]
+    fn test_refers_to() {
+        let import = |rust_file: &str, parent: &[&str], module: &str| StyleImport {
+            rust_file: rust_file.into(),
+            parent: strings(parent),
+            module: module.into(),
+            css_file: PathBuf::new(),
+        };
+        let card = import("src/card.rs", &["crate", "card"], "style");
+        let nested = import("src/card.rs", &["crate", "card", "inner"], "style");
+        let header = import("src/header.rs", &["crate", "header"], "style");
+
+        let mut file = RustFile {
+            path: "src/card.rs".into(),
+            module_path: strings(&["crate", "card"]),
+            uses: HashMap::new(),
+            glob_uses: Vec::new(),
+            references: References::default(),
+        };
+        assert!(file.refers_to(&strings(&["style"]), &card));
+        assert!(file.refers_to(&strings(&["style"]), &nested));
+        assert!(!file.refers_to(&strings(&["style"]), &header));
+        assert!(file.refers_to(&strings(&["super", "header", "style"]), &header));
+        assert!(file.refers_to(&strings(&["crate", "header", "style"]), &header));
+        assert!(file.refers_to(&strings(&["my_app", "header", "style"]), &header));
+        assert!(!file.refers_to(&strings(&["crate", "footer", "style"]), &header));
+
+        file.uses.insert(
+            "header_style".into(),
+            vec![strings(&["crate", "header", "style"])],
+        );
+        assert!(file.refers_to(&strings(&["header_style"]), &header));
+    }
+
+    #[test]
+    fn test_identical_modules() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        fs::create_dir_all(&src).unwrap();
+        let css = ".a { composes: b; }\n.b { color: red; }\n";
+        fs::write(src.join("one.module.css"), css).unwrap();
+        fs::write(src.join("two.module.css"), css).unwrap();
+        fs::write(
+            src.join("lib.rs"),
+            "stylance::import_crate_style!(one, \"src/one.module.css\");\n\
+             stylance::import_crate_style!(two, \"src/two.module.css\");\n\
+             const A: &str = one::a;\n",
+        )
+        .unwrap();
+        let config = Config::from_partials(
+            dir.path().to_path_buf(),
+            PartialConfig {
+                hash_strategy: Some(HashStrategy::Content),
+                ..Default::default()
+            },
+            None,
+        )
+        .unwrap();
+
+        // Both modules have the same scoped names, only the referenced one is used.
+        let unused = find_unused_classes(&config)
+            .unwrap()
+            .into_iter()
+            .map(|class| (class.css_file, class.name))
+            .collect::<Vec<_>>();
+        let two = path_utils::normalize(src.join("two.module.css")).unwrap();
+        assert_eq!(
+            unused,
+            [(two.clone(), "a".to_owned()), (two, "b".to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_adjusted_identifiers() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(
+            src.join("style.module.css"),
+            ".type { color: red; }\n.self { color: red; }\n.-2col { color: red; }\n.unused { color: red; }\n",
+        )
+        .unwrap();
+        fs::write(
+            src.join("lib.rs"),
+            "stylance::import_crate_style!(style, \"src/style.module.css\");\n\
+             const A: &str = style::r#type;\n\
+             const B: &str = style::self_;\n\
+             const C: &str = style::_2col;\n",
+        )
+        .unwrap();
+        let config =
+            Config::from_partials(dir.path().to_path_buf(), Default::default(), None).unwrap();
+
+        let unused = find_unused_classes(&config)
+            .unwrap()
+            .into_iter()
+            .map(|class| class.name)
+            .collect::<Vec<_>>();
+        assert_eq!(unused, ["unused"]);
+    }
+}
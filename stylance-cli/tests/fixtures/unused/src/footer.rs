@@ -0,0 +1,5 @@
+stylance::import_style!(style, "footer.module.css");
+
+pub fn footer() -> &'static str {
+    style::footer
+}
@@ -0,0 +1,8 @@
+mod footer;
+mod nested;
+
+stylance::import_crate_style!(#[allow(dead_code)] style, "src/card.module.css");
+
+pub fn view() -> String {
+    format!("{} {}", style::card, style::card_title)
+}
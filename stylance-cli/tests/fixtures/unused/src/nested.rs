@@ -0,0 +1,7 @@
+stylance::import_style!(pub theme, "theme.module.css");
+
+use theme::{dark};
+
+pub fn theme() -> &'static str {
+    dark
+}
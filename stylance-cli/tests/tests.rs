@@ -140,6 +140,31 @@ fn test_multiple_output_file_cli_override() {
     );
 }
 
+#[test]
+fn test_exports() {
+    let setup = setup();
+
+    let exports = setup.path("exports");
+
+    let output = setup
+        .command()
+        .arg(&exports)
+        .output()
+        .expect("failed to execute stylance binary");
+
+    assert!(
+        output.status.success(),
+        "stylance with :export blocks failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // The `:export` block is removed from the output
+    assert_files_are_equal(
+        &exports.join("output.css"),
+        &exports.join("expected_output.css"),
+    );
+}
+
 #[test]
 fn test_workspace() {
     let setup = setup();
@@ -164,3 +189,136 @@ fn test_workspace() {
         &workspace.join("expected_output.css"),
     );
 }
+
+#[test]
+fn test_check() {
+    let setup = setup();
+
+    let crate1 = setup.path("crate1");
+    let crate2 = setup.path("crate2");
+
+    let check = || {
+        setup
+            .command()
+            .arg("check")
+            .arg(&crate1)
+            .arg(&crate2)
+            .output()
+            .expect("failed to execute stylance binary")
+    };
+
+    // Nothing was generated yet.
+    let output = check();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("output.css"));
+    assert!(!crate1.join("output.css").exists());
+
+    let output = setup
+        .command()
+        .arg(&crate1)
+        .arg(&crate2)
+        .output()
+        .expect("failed to execute stylance binary");
+    assert!(output.status.success());
+
+    let output = check();
+    assert!(
+        output.status.success(),
+        "check should pass after generating the output: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // Editing a css module makes the output stale.
+    let button = crate1.join("src/button.module.css");
+    let contents = fs::read_to_string(&button).unwrap();
+    fs::write(&button, format!("{contents}\n.new {{ color: red; }}\n")).unwrap();
+    assert!(!check().status.success());
+
+    // Parse errors are reported too.
+    fs::write(&button, ".broken {").unwrap();
+    let output = check();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("button.module.css:1:"));
+}
+
+#[test]
+fn test_manifest() {
+    let setup = setup();
+
+    let crate1 = setup.path("crate1");
+
+    let output = setup
+        .command()
+        .arg(&crate1)
+        .arg("--manifest")
+        .arg(crate1.join("manifest.json"))
+        .output()
+        .expect("failed to execute stylance binary");
+
+    assert!(
+        output.status.success(),
+        "stylance with --manifest failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    assert_files_are_equal(
+        &crate1.join("manifest.json"),
+        &crate1.join("expected_manifest.json"),
+    );
+}
+
+#[test]
+fn test_source_map() {
+    let setup = setup();
+
+    let crate1 = setup.path("crate1");
+
+    let output = setup
+        .command()
+        .arg(&crate1)
+        .arg("--source-map")
+        .output()
+        .expect("failed to execute stylance binary");
+
+    assert!(
+        output.status.success(),
+        "stylance with --source-map failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let css = fs::read_to_string(crate1.join("output.css")).unwrap();
+    assert!(css.ends_with("\n/*# sourceMappingURL=output.css.map */\n"));
+
+    let map: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(crate1.join("output.css.map")).unwrap())
+            .expect("source map should be valid json");
+    assert_eq!(map["version"], 3);
+    assert_eq!(map["file"], "output.css");
+    assert_eq!(map["sources"], serde_json::json!(["src/button.module.css"]));
+    assert_eq!(map["names"], serde_json::json!(["container", "title"]));
+    assert_eq!(
+        map["mappings"],
+        "AAAA,CAACA,iBAAS;AACV;AACA;AACA;AACA,CAACC,aAAK;AACN;AACA"
+    );
+}
+
+#[test]
+fn test_unused() {
+    let setup = setup();
+
+    let output = setup
+        .command()
+        .current_dir(setup.path("unused"))
+        .arg("unused")
+        .arg(".")
+        .output()
+        .expect("failed to execute stylance binary");
+
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "src/card.module.css:14:2: unused class `unused-one`\n\
+         src/footer.module.css:5:2: unused class `card-title`\n\
+         src/theme.module.css:5:2: unused class `light`\n"
+    );
+}
@@ -0,0 +1,171 @@
+//! Generates the style modules from a build script, as an alternative to the
+//! [`import_style!`](crate::import_style) and [`import_crate_style!`](crate::import_crate_style) macros.
+//!
+//! ```rust,ignore
+//! // build.rs
+//! fn main() {
+//!     stylance::build::generate().expect("failed to generate stylance modules");
+//! }
+//! ```
+//!
+//! Then include the generated file from your crate. There is one module per css module file,
+//! nested following the folders of the file:
+//!
+//! ```rust
+//! mod style {
+//!     include!(concat!(env!("OUT_DIR"), "/stylance_generated.rs"));
+//! }
+//!
+//! // src/components/card.module.css
+//! let header = style::components::card::header;
+//! ```
+//!
+//! The modules are generated from the css module files found in the `folders` of the stylance
+//! configuration in Cargo.toml, the path of each module is relative to the folder it was found in.
+
+use std::{
+    collections::BTreeMap,
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context as _};
+use proc_macro2::Span;
+pub use stylance_core::Config;
+use stylance_core::{codegen, path_utils};
+
+/// Name of the file written to `OUT_DIR` by [`generate`].
+pub const GENERATED_FILE_NAME: &str = "stylance_generated.rs";
+
+/// Generates `stylance_generated.rs` in `OUT_DIR` for the crate being built.
+///
+/// Must be called from a build script, it prints the `cargo:rerun-if-changed` lines needed to
+/// regenerate the file when a css module or the configuration changes.
+pub fn generate() -> anyhow::Result<()> {
+    let manifest_dir = env::var_os("CARGO_MANIFEST_DIR")
+        .context("CARGO_MANIFEST_DIR env var not found, generate must be called from build.rs")?;
+    let out_dir = env::var_os("OUT_DIR")
+        .context("OUT_DIR env var not found, generate must be called from build.rs")?;
+
+    let config = Config::load(PathBuf::from(manifest_dir))?;
+    for path in generate_to(&config, &Path::new(&out_dir).join(GENERATED_FILE_NAME))? {
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+
+    Ok(())
+}
+
+/// Writes the generated modules of a crate to `out_file`.
+///
+/// Returns the files and folders that were read, the output must be generated again when any of them changes.
+pub fn generate_to(config: &Config, out_file: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut dependencies = vec![config.manifest_dir.join("Cargo.toml")];
+    if let Some(workspace_dir) = &config.workspace_dir {
+        dependencies.push(workspace_dir.join("Cargo.toml"));
+    }
+    // New css module files show up as changes to the folders.
+    dependencies.extend(config.folders.iter().cloned());
+
+    let manifest_dir = path_utils::normalize(&config.manifest_dir)?;
+    let mut root = ModuleTree::default();
+    for css_file in stylance_core::find_css_modules(config) {
+        let css_file = path_utils::normalize(&css_file)?;
+        let relative_path = path_utils::diff_normalized_paths(&css_file, &manifest_dir)?;
+        let classes = stylance_core::get_classes(&css_file, config)?;
+        dependencies.push(css_file.clone());
+        dependencies.extend(classes.dependencies.iter().cloned());
+
+        let module_path = module_path(config, &css_file)?;
+        let (name, parents) = module_path
+            .split_last()
+            .expect("module path can't be empty");
+        let conflict = || {
+            anyhow::anyhow!(
+                "{} would generate the module `{}` which already exists",
+                css_file.to_string_lossy(),
+                module_path.join("::")
+            )
+        };
+
+        let mut parent = &mut root;
+        for folder in parents {
+            if parent.modules.contains_key(folder) {
+                return Err(conflict());
+            }
+            parent = parent.children.entry(folder.clone()).or_default();
+        }
+        if parent.children.contains_key(name) || parent.modules.contains_key(name) {
+            return Err(conflict());
+        }
+        let items = codegen::module_items(&classes, Span::call_site());
+        let registration = codegen::registration(&css_file, config, &classes)?;
+        let contents = format!("{items}\n{registration}\n");
+        parent
+            .modules
+            .insert(name.clone(), (relative_path, contents));
+    }
+
+    dependencies.sort();
+    dependencies.dedup();
+
+    fs::write(out_file, root.to_string())
+        .with_context(|| format!("Failed to write {}", out_file.to_string_lossy()))?;
+
+    Ok(dependencies)
+}
+
+/// Module identifiers of a css file, from the path relative to the folder it was found in.
+fn module_path(config: &Config, css_file: &Path) -> anyhow::Result<Vec<String>> {
+    for folder in &config.folders {
+        let folder = path_utils::normalize(folder)?;
+        if let Ok(relative) = css_file.strip_prefix(&folder) {
+            let components = relative
+                .iter()
+                .map(|c| c.to_string_lossy())
+                .collect::<Vec<_>>();
+            let (file_name, folders) = components
+                .split_last()
+                .expect("css file is inside of the folder");
+            // Remove every extension of the file, `card.module.css` becomes `card`.
+            let stem = file_name.split('.').next().unwrap_or_default();
+
+            return Ok(folders
+                .iter()
+                .map(|folder| folder.as_ref())
+                .chain([stem])
+                .map(|name| codegen::ident(name, Span::call_site()).to_string())
+                .collect());
+        }
+    }
+
+    bail!(
+        "{} is not inside of the stylance folders",
+        css_file.to_string_lossy()
+    )
+}
+
+/// Generated modules, nested following the folders of the css files.
+#[derive(Default)]
+struct ModuleTree {
+    children: BTreeMap<String, ModuleTree>,
+    /// Module name -> (css file relative to the manifest dir, generated items).
+    modules: BTreeMap<String, (PathBuf, String)>,
+}
+
+impl fmt::Display for ModuleTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, (css_file, contents)) in &self.modules {
+            let css_file = css_file
+                .iter()
+                .map(|c| c.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            writeln!(f, "/// Generated from `{css_file}`.")?;
+            writeln!(f, "#[allow(dead_code)]\npub mod {name} {{\n{contents}}}")?;
+        }
+        for (name, child) in &self.children {
+            writeln!(f, "pub mod {name} {{\n{child}}}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,47 @@
+//! Access to the css of the modules imported with `embed_css` enabled.
+//!
+//! When the `embed_css` setting is enabled the import macros generate a `CSS` constant
+//! with the transformed stylesheet of the module and register it here. This allows serving
+//! or injecting the styles without a separate css bundle:
+//!
+//! ```rust
+//! let style_tag = format!("<style>{}</style>", stylance::embed::css());
+//! ```
+//!
+//! Only `.css` modules can be embedded, importing a sass module with `embed_css` enabled
+//! is a compile error since stylance doesn't compile sass.
+//!
+//! Like [`registry`](crate::registry) it needs the `registry` feature, and on wasm the
+//! embedded modules are registered by the static constructors described there.
+
+/// The css of a module imported with `embed_css` enabled.
+#[derive(Debug)]
+pub struct EmbeddedStyle {
+    /// Path of the css module relative to `hash_root_path`, with `/` separators.
+    pub path: &'static str,
+    /// Hash of the module, the same one used in its scoped class names.
+    pub hash: &'static str,
+    /// Transformed css of the module.
+    pub css: &'static str,
+}
+
+inventory::collect!(EmbeddedStyle);
+
+/// Returns the embedded modules of every crate linked into the binary, sorted by path.
+///
+/// A module imported more than once is only returned once.
+pub fn styles() -> Vec<&'static EmbeddedStyle> {
+    let mut styles = inventory::iter::<EmbeddedStyle>().collect::<Vec<_>>();
+    styles.sort_by_key(|style| (style.path, style.hash));
+    styles.dedup_by_key(|style| (style.path, style.hash));
+    styles
+}
+
+/// Concatenates the css of all the embedded modules, like the output file of stylance cli.
+pub fn css() -> String {
+    styles()
+        .iter()
+        .map(|style| style.css)
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
@@ -56,6 +56,16 @@
 
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+#[cfg(feature = "build")]
+#[cfg_attr(docsrs, doc(cfg(feature = "build")))]
+pub mod build;
+#[cfg(feature = "registry")]
+#[cfg_attr(docsrs, doc(cfg(feature = "registry")))]
+pub mod embed;
+#[cfg(feature = "registry")]
+#[cfg_attr(docsrs, doc(cfg(feature = "registry")))]
+pub mod registry;
+
 #[doc(hidden)]
 pub mod internal {
     /// MaybeStr Wraps an Option<&str> and implements From trait for various
@@ -63,8 +73,30 @@ pub mod internal {
     /// Used by JoinClasses and the classes! macro to accept various types.
     pub struct MaybeStr<'a>(Option<&'a str>);
 
+    #[cfg(feature = "registry")]
+    pub use inventory;
     pub use stylance_macros::*;
 
+    pub use crate::__register as register;
+
+    /// Submits the registration generated for a style module, it expands to nothing
+    /// without the `registry` feature.
+    #[cfg(feature = "registry")]
+    #[macro_export]
+    #[doc(hidden)]
+    macro_rules! __register {
+        ($($registration:tt)*) => {
+            $crate::internal::inventory::submit! { $($registration)* }
+        };
+    }
+
+    #[cfg(not(feature = "registry"))]
+    #[macro_export]
+    #[doc(hidden)]
+    macro_rules! __register {
+        ($($registration:tt)*) => {};
+    }
+
     fn join_opt_str_iter<'a, Iter>(iter: &mut Iter) -> String
     where
         Iter: Iterator<Item = &'a str> + Clone,
@@ -344,3 +376,20 @@ macro_rules! classes {
         ::stylance::JoinClasses::join_classes([$($exp.into()),*].as_slice())
     };
 }
+
+/// Returns a javascript snippet that connects to the live reload server of `stylance --watch --serve <port>`
+/// and swaps the stylesheets in the page when the output file changes, without reloading the page.
+///
+/// The stylesheet to replace is found by the file name of the output file, stylesheets that can't
+/// be found get appended to the document head.
+///
+/// ### Example
+///
+/// ```rust
+/// // Only include the live reload snippet in development builds.
+/// #[cfg(debug_assertions)]
+/// let script = format!("<script>{}</script>", stylance::live_reload_script(3001));
+/// ```
+pub fn live_reload_script(port: u16) -> String {
+    format!("({})({port});", include_str!("live_reload.js").trim_end())
+}
@@ -0,0 +1,27 @@
+(port) => {
+  const events = new EventSource(`http://127.0.0.1:${port}/events`);
+
+  events.addEventListener("css", (event) => {
+    const { file, css } = JSON.parse(event.data);
+
+    let style = document.querySelector(`style[data-stylance-file="${file}"]`);
+    if (!style) {
+      style = document.createElement("style");
+      style.dataset.stylanceFile = file;
+
+      // Replace the stylesheet linking to the output file, otherwise the new styles
+      // are appended and take precedence over the old ones.
+      const link = Array.from(document.querySelectorAll('link[rel="stylesheet"]')).find(
+        (link) => new URL(link.href, location.href).pathname.split("/").pop() === file,
+      );
+      if (link) {
+        link.after(style);
+        link.disabled = true;
+      } else {
+        document.head.append(style);
+      }
+    }
+
+    style.textContent = css;
+  });
+}
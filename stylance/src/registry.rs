@@ -0,0 +1,59 @@
+//! Registry of the style modules linked into the binary.
+//!
+//! Every module imported with [`import_style!`](crate::import_style) or
+//! [`import_crate_style!`](crate::import_crate_style), or generated from a build script, registers
+//! itself here. Only the modules of the crates linked into the binary are registered, which
+//! allows server side rendering to send the styles of those crates and nothing else:
+//!
+//! ```rust
+//! for module in stylance::registry::modules() {
+//!     println!("{} ({} classes)", module.path, module.classes.len());
+//! }
+//! ```
+//!
+//! The css of a module is only available when the `embed_css` setting is enabled.
+//!
+//! This module needs the `registry` feature, without it the modules aren't registered.
+//!
+//! Modules are registered through the `inventory` crate, which runs a static constructor
+//! for each of them. On `wasm32-unknown-unknown` the constructors only run when the wasm
+//! module calls `__wasm_call_ctors`: the linker inserts that call at the start of the exported
+//! functions of modules that are called into once, like the ones built by wasm-bindgen,
+//! otherwise the embedder must call it right after instantiating the module. [`modules`]
+//! returns nothing until the constructors have run.
+
+/// A style module imported by a crate linked into the binary.
+#[derive(Debug, PartialEq)]
+pub struct Module {
+    /// Path of the css module relative to `hash_root_path`, with `/` separators.
+    pub path: &'static str,
+    /// Hash of the module, the same one used in its scoped class names.
+    pub hash: &'static str,
+    /// Classes of the module, sorted by their original name.
+    pub classes: &'static [Class],
+    /// Transformed css of the module, when imported with `embed_css` enabled.
+    pub css: Option<&'static str>,
+}
+
+/// A class of a registered module.
+#[derive(Debug, PartialEq)]
+pub struct Class {
+    /// Name of the class in the css file.
+    pub original_name: &'static str,
+    /// Scoped name of the class.
+    pub hashed_name: &'static str,
+    /// Scoped names of the classes it composes.
+    pub composes: &'static [&'static str],
+}
+
+inventory::collect!(Module);
+
+/// Returns the modules of every crate linked into the binary, sorted by path.
+///
+/// A module imported more than once is only returned once.
+pub fn modules() -> Vec<&'static Module> {
+    let mut modules = inventory::iter::<Module>().collect::<Vec<_>>();
+    modules.sort_by_key(|module| (module.path, module.hash));
+    modules.dedup();
+    modules
+}
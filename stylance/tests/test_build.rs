@@ -0,0 +1,147 @@
+#![cfg(feature = "build")]
+
+use std::fs;
+
+use stylance::build::{generate_to, Config};
+
+/// Normalizes the formatting of rust code, the generated code isn't formatted.
+fn tokens(code: &str) -> String {
+    code.parse::<proc_macro2::TokenStream>()
+        .unwrap()
+        .to_string()
+}
+
+#[test]
+fn test_generate_modules() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    fs::create_dir_all(root.join("src/components")).unwrap();
+    fs::write(
+        root.join("Cargo.toml"),
+        "[package]\nname = \"generated\"\n\n[package.metadata.stylance]\nhash_len = 4\n",
+    )
+    .unwrap();
+    fs::write(
+        root.join("src/components/card.module.css"),
+        ".card-header { composes: base from \"../base.module.scss\"; }\n@keyframes fade {}\n",
+    )
+    .unwrap();
+    fs::write(root.join("src/base.module.scss"), ".base { color: red; }\n").unwrap();
+
+    let config = Config::load(root.to_path_buf()).unwrap();
+    let out_file = root.join("stylance_generated.rs");
+    let dependencies = generate_to(&config, &out_file).unwrap();
+
+    let contents = fs::read_to_string(&out_file).unwrap();
+    let base_hash = &contents[contents.find("base-").unwrap() + 5..][..4];
+    let card_hash = &contents[contents.find("card-header-").unwrap() + 12..][..4];
+    let expected = format!(
+        r#"/// Generated from `src/base.module.scss`.
+#[allow(dead_code)]
+pub mod base {{
+#[allow(non_upper_case_globals)]
+pub const base: &str = "base-{base_hash}";
+::stylance::internal::register! {{
+    ::stylance::registry::Module {{
+        path: "src/base.module.scss",
+        hash: "{base_hash}",
+        classes: &[
+            ::stylance::registry::Class {{
+                original_name: "base",
+                hashed_name: "base-{base_hash}",
+                composes: &[],
+            }},
+        ],
+        css: ::core::option::Option::None,
+    }}
+}}
+}}
+pub mod components {{
+/// Generated from `src/components/card.module.css`.
+#[allow(dead_code)]
+pub mod card {{
+#[allow(non_upper_case_globals)]
+pub const card_header: &str = "card-header-{card_hash} base-{base_hash}";
+#[allow(dead_code)]
+pub mod keyframes {{
+#[allow(non_upper_case_globals)]
+pub const fade: &str = "fade-{card_hash}";
+}}
+::stylance::internal::register! {{
+    ::stylance::registry::Module {{
+        path: "src/components/card.module.css",
+        hash: "{card_hash}",
+        classes: &[
+            ::stylance::registry::Class {{
+                original_name: "card-header",
+                hashed_name: "card-header-{card_hash}",
+                composes: &["base-{base_hash}"],
+            }},
+        ],
+        css: ::core::option::Option::None,
+    }}
+}}
+}}
+}}
+"#,
+    );
+    assert_eq!(tokens(&contents), tokens(&expected));
+
+    assert!(dependencies.contains(&root.join("Cargo.toml")));
+    assert!(dependencies.contains(&root.join("src/components/card.module.css")));
+
+    // A css file and a folder can't generate the same module.
+    fs::write(root.join("src/components.module.css"), ".a {}\n").unwrap();
+    assert!(generate_to(&config, &out_file).is_err());
+}
+
+#[test]
+fn test_generate_modules_dotted_folders() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    fs::create_dir_all(root.join("src/v1.2")).unwrap();
+    fs::create_dir_all(root.join("src/v1.3")).unwrap();
+    fs::write(root.join("Cargo.toml"), "[package]\nname = \"generated\"\n").unwrap();
+    fs::write(root.join("src/v1.2/card.module.css"), ".a {}\n").unwrap();
+    fs::write(root.join("src/v1.3/card.module.css"), ".a {}\n").unwrap();
+
+    let config = Config::load(root.to_path_buf()).unwrap();
+    let out_file = root.join("stylance_generated.rs");
+    generate_to(&config, &out_file).unwrap();
+
+    let contents = fs::read_to_string(&out_file).unwrap();
+    assert!(contents.contains("pub mod v1_2 {"));
+    assert!(contents.contains("pub mod v1_3 {"));
+}
+
+#[test]
+fn test_generate_embedded_modules() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::write(
+        root.join("Cargo.toml"),
+        "[package]\nname = \"generated\"\n\n[package.metadata.stylance]\nembed_css = true\n",
+    )
+    .unwrap();
+    fs::write(root.join("src/card.module.css"), ".card { color: red; }\n").unwrap();
+
+    let config = Config::load(root.to_path_buf()).unwrap();
+    let out_file = root.join("stylance_generated.rs");
+    generate_to(&config, &out_file).unwrap();
+
+    let contents = fs::read_to_string(&out_file).unwrap();
+    let hash = &contents[contents.find("card-").unwrap() + 5..][..7];
+    let css = format!(".card-{hash} {{ color: red; }}\n");
+    assert!(tokens(&contents).contains(&tokens(&format!("pub const CSS: &str = {css:?};"))));
+    assert!(tokens(&contents).contains(&tokens("css: ::core::option::Option::Some(CSS)")));
+
+    // Sass modules aren't compiled, they can't be embedded.
+    fs::write(
+        root.join("src/other.module.scss"),
+        ".other { color: red; }\n",
+    )
+    .unwrap();
+    let err = generate_to(&config, &out_file).unwrap_err();
+    assert!(format!("{err:#}").contains("can't be embedded"));
+}
@@ -55,3 +55,47 @@ fn test_import_style() {
     assert_eq!(style2::style1, "style1-58ea9e3");
     assert_eq!(style2::different_style, "different-style-58ea9e3");
 }
+
+#[test]
+fn test_import_style_composes() {
+    import_style!(style, "composes.module.scss");
+
+    assert_eq!(style::base, "base-a467565");
+    assert_eq!(style::primary, "primary-a467565 base-a467565");
+    assert_eq!(
+        style::large_primary,
+        "large-primary-a467565 primary-a467565 base-a467565 different-style-58ea9e3"
+    );
+    assert_eq!(
+        style::external,
+        "external-a467565 some-global-class other-global"
+    );
+}
+
+#[test]
+fn test_import_style_keyframes() {
+    import_style!(style, "keyframes.module.scss");
+
+    assert_eq!(style::spinner, "spinner-408621d");
+    assert_eq!(style::fader, "fader-408621d");
+    assert_eq!(style::keyframes::spin, "spin-408621d");
+    assert_eq!(style::keyframes::fade_in, "fade-in-408621d");
+}
+
+#[test]
+fn test_import_style_identifiers() {
+    import_style!(style, "names.module.css");
+
+    assert_eq!(style::r#type, "type-aa8d0ac");
+    assert_eq!(style::self_, "self-aa8d0ac");
+    assert_eq!(style::_2col, "-2col-aa8d0ac");
+}
+
+#[test]
+fn test_import_style_exports() {
+    import_style!(style, "export.module.scss");
+
+    assert_eq!(style::container, "container-4d4a8a5");
+    assert_eq!(style::exports::breakpoint, "768px");
+    assert_eq!(style::exports::primary_color, "#f00");
+}
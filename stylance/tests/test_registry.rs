@@ -0,0 +1,60 @@
+#![cfg(feature = "registry")]
+
+use stylance::*;
+
+// The css of a module embedded by two crates, it is only returned once.
+stylance::internal::inventory::submit! {
+    stylance::embed::EmbeddedStyle {
+        path: "tests/embedded.module.css",
+        hash: "58ea9e3",
+        css: ".embedded-58ea9e3 { color: red; }",
+    }
+}
+stylance::internal::inventory::submit! {
+    stylance::embed::EmbeddedStyle {
+        path: "tests/embedded.module.css",
+        hash: "58ea9e3",
+        css: ".embedded-58ea9e3 { color: red; }",
+    }
+}
+
+#[test]
+fn test_embed_css() {
+    let embedded = stylance::embed::styles()
+        .into_iter()
+        .filter(|s| s.path == "tests/embedded.module.css")
+        .collect::<Vec<_>>();
+    assert_eq!(embedded.len(), 1, "modules are only returned once");
+    assert_eq!(embedded[0].hash, "58ea9e3");
+
+    assert!(stylance::embed::css().contains(".embedded-58ea9e3 { color: red; }"));
+}
+
+#[test]
+fn test_registry() {
+    import_style!(
+        #[allow(dead_code)]
+        style,
+        "composes.module.scss"
+    );
+
+    let modules = stylance::registry::modules();
+    let module = modules
+        .iter()
+        .find(|m| m.path == "tests/composes.module.scss")
+        .expect("imported modules are registered");
+    assert_eq!(module.hash, "a467565");
+    assert_eq!(module.css, None, "embed_css is disabled");
+
+    let primary = module
+        .classes
+        .iter()
+        .find(|c| c.original_name == "primary")
+        .unwrap();
+    assert_eq!(primary.hashed_name, "primary-a467565");
+    assert_eq!(primary.composes, ["base-a467565"]);
+
+    assert!(modules
+        .windows(2)
+        .all(|w| (w[0].path, w[0].hash) < (w[1].path, w[1].hash)));
+}